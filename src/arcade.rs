@@ -0,0 +1,152 @@
+use crate::*;
+use bevy::prelude::*;
+use simple_easing::quad_in;
+
+/// Time attack. The board is played against a countdown that drains faster
+/// the longer the round goes on, clearing circle tiles wins back a little
+/// time and the round is over once the clock runs out.
+pub struct ArcadePlugin;
+
+impl Plugin for ArcadePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::InGame),
+            (start_arcade, spawn_arcade_hud).run_if(in_arcade_mode),
+        )
+        .add_systems(
+            OnExit(GameState::InGame),
+            (end_arcade, despawn_screen::<ArcadeText>),
+        )
+        .add_systems(
+            Update,
+            (
+                start_arcade_clock,
+                add_time_bonus,
+                tick_arcade_timer.run_if(in_state(PauseState::Running)),
+                check_time_up.run_if(in_state(ResolveState::Idle)),
+                update_arcade_hud,
+            )
+                .chain()
+                .run_if(resource_exists::<ArcadeTimer>)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+pub fn in_arcade_mode(config: Res<GameConfig>) -> bool {
+    config.mode == GameMode::Arcade
+}
+
+/// The countdown of an arcade round.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct ArcadeTimer {
+    pub time_left: f32,
+    /// Seconds played so far, which sets how fast the clock drains.
+    pub elapsed: f32,
+    /// The clock only starts once the board has been dealt and has settled.
+    pub running: bool,
+}
+
+impl Default for ArcadeTimer {
+    fn default() -> Self {
+        Self {
+            time_left: ARCADE_TIME,
+            elapsed: 0.0,
+            running: false,
+        }
+    }
+}
+
+impl ArcadeTimer {
+    /// Seconds lost from the clock per second played. It starts at one and
+    /// eases up to ARCADE_MAX_SPEEDUP over ARCADE_SPEEDUP_TIME.
+    pub fn drain_rate(&self) -> f32 {
+        let progress = (self.elapsed / ARCADE_SPEEDUP_TIME).min(1.0);
+        1.0 + (ARCADE_MAX_SPEEDUP - 1.0) * quad_in(progress)
+    }
+
+    pub fn tick(&mut self, seconds: f32) {
+        if !self.running {
+            return;
+        }
+        self.time_left = (self.time_left - seconds * self.drain_rate()).max(0.0);
+        self.elapsed += seconds;
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.time_left <= 0.0
+    }
+}
+
+#[derive(Component)]
+pub struct ArcadeText;
+
+pub fn start_arcade(mut commands: Commands) {
+    commands.insert_resource(ArcadeTimer::default());
+    info!("Started an arcade round of {} seconds", ARCADE_TIME);
+}
+
+pub fn end_arcade(mut commands: Commands) {
+    commands.remove_resource::<ArcadeTimer>();
+}
+
+pub fn start_arcade_clock(
+    mut settled: EventReader<BoardSettledEvent>,
+    mut timer: ResMut<ArcadeTimer>,
+) {
+    if settled.is_empty() {
+        return;
+    }
+    settled.clear();
+    if !timer.running {
+        timer.running = true;
+        info!("Arcade clock started");
+    }
+}
+
+pub fn add_time_bonus(mut matched: EventReader<TilesMatchedEvent>, mut timer: ResMut<ArcadeTimer>) {
+    for event in matched.read() {
+        let bonus: f32 = event
+            .cleared
+            .iter()
+            .map(|tile| tile.mark.bonus_seconds())
+            .sum();
+        if bonus > 0.0 {
+            timer.time_left += bonus;
+            info!("Added {} seconds to the clock", bonus);
+        }
+    }
+}
+
+pub fn tick_arcade_timer(mut timer: ResMut<ArcadeTimer>, time: Res<Time>) {
+    timer.tick(time.delta_seconds());
+}
+
+/// The clock can run out mid-cascade, but the round only ends once the board
+/// is idle, so the cascade's points and time bonuses still count.
+pub fn check_time_up(timer: Res<ArcadeTimer>, mut next_state: ResMut<NextState<GameState>>) {
+    if timer.is_up() {
+        next_state.set(GameState::GameOver);
+        info!("Time's up after {:.1} seconds", timer.elapsed);
+    }
+}
+
+pub fn spawn_arcade_hud(mut commands: Commands) {
+    spawn_mode_hud(&mut commands, "Arcade", ArcadeText);
+}
+
+pub fn update_arcade_hud(
+    timer: Res<ArcadeTimer>,
+    mut text_query: Query<&mut Text, With<ArcadeText>>,
+) {
+    if !timer.is_changed() {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!(
+            "Time: {}\nSpeed: x{:.1}",
+            timer.time_left.ceil(),
+            timer.drain_rate()
+        );
+    }
+}
@@ -1,7 +1,12 @@
 use crate::*;
 use bevy::math::prelude::*;
 use bevy::utils::hashbrown::HashMap;
-use bevy::utils::HashSet;
+use std::collections::HashSet;
+use std::ops::DerefMut;
+
+/// How many rounds of rerolls the deal gets before it leaves the remaining
+/// matches for the first cascade to clear.
+const MAX_DEAL_REROLLS: u32 = 100;
 
 /* const SQUARE_COORD: [(i8, i8); 8] = [
     // Bottom left
@@ -24,64 +29,131 @@ use bevy::utils::HashSet;
 
 #[derive(Resource)]
 pub struct GameBoard {
-    pub dimensions: UVec2,
-    pub forward: Vec<Option<TileDesc>>,
+    pub board: Board,
     pub backward: HashMap<usize, Entity>,
+    pub obstacle_sprites: HashMap<usize, Entity>,
     pub origin: Vec2,
     pub entity: Entity,
+    pub cascade: u32,
+    pub swap_focus: Vec<usize>,
+    pub triggered: HashSet<usize>,
+    pub swap_duration: f32,
+    pub fall_duration: f32,
+    pub clear_duration: f32,
+}
+
+/// Sent once the board has come to rest after a swap or cascade.
+#[derive(Event, Debug)]
+pub struct BoardSettledEvent;
+
+/// Sent whenever the resolve pipeline moves on to another phase.
+#[derive(Event, Debug)]
+pub struct ResolvePhaseEvent {
+    pub phase: ResolveState,
+}
+
+/// Sent for every resolve step that cleared tiles. The cascade counts the
+/// steps since the player's last swap, starting at 1.
+#[derive(Event, Debug)]
+pub struct TilesMatchedEvent {
+    pub runs: Vec<Vec<usize>>,
+    pub removed: Vec<usize>,
+    pub cleared: Vec<TileDesc>,
+    /// Cells of ingredients that reached the bottom.
+    pub collected: Vec<usize>,
+    pub cascade: u32,
 }
 
 pub struct GameBoardPlugin;
 
 impl Plugin for GameBoardPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, create_gameboard)
-            .add_systems(PostStartup, fill_gameboard)
+        app.init_state::<ResolveState>()
+            .add_event::<TilesMatchedEvent>()
+            .add_event::<BoardSettledEvent>()
+            .add_event::<ResolvePhaseEvent>()
+            .add_systems(
+                OnEnter(GameState::InGame),
+                (
+                    seed_game_rng,
+                    apply_deferred,
+                    create_gameboard,
+                    apply_deferred,
+                    fill_gameboard,
+                    start_resolving,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                OnExit(GameState::InGame),
+                (teardown_gameboard, stop_resolving),
+            )
+            .add_systems(
+                OnEnter(ResolveState::Idle),
+                settle_board.run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                OnEnter(ResolveState::Matching),
+                find_board_matches.run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                OnEnter(ResolveState::Falling),
+                drop_tiles.run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                OnEnter(ResolveState::Refilling),
+                refill_board.run_if(in_state(GameState::InGame)),
+            )
             .add_systems(
                 Update,
-                match_remove_refill.run_if(in_state(MoveState::NotMoving)),
+                (
+                    announce_resolve_phase.run_if(state_changed::<ResolveState>),
+                    swap_processor
+                        .after(click_processor)
+                        .run_if(in_state(ResolveState::Idle)),
+                    finish_swap.run_if(in_state(ResolveState::Swapping)),
+                    advance_when_still(ResolveState::Falling)
+                        .run_if(in_state(ResolveState::Clearing)),
+                    advance_when_still(ResolveState::Refilling)
+                        .run_if(in_state(ResolveState::Falling)),
+                    finish_refilling.run_if(in_state(ResolveState::Refilling)),
+                )
+                    .run_if(in_state(GameState::InGame)),
             );
     }
 }
 
-pub trait Index2D<RHS = Self> {
-    fn idx(&self, grid_pos: RHS) -> usize;
-}
+impl Deref for GameBoard {
+    type Target = Board;
 
-impl Index2D<(u32, u32)> for GameBoard {
-    fn idx(&self, grid_pos: (u32, u32)) -> usize {
-        grid_pos.1 as usize * self.dimensions.x as usize + grid_pos.0 as usize
+    fn deref(&self) -> &Self::Target {
+        &self.board
     }
 }
 
-impl Index2D<UVec2> for GameBoard {
-    fn idx(&self, grid_pos: UVec2) -> usize {
-        grid_pos.y as usize * self.dimensions.x as usize + grid_pos.x as usize
+impl DerefMut for GameBoard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.board
     }
 }
 
 impl GameBoard {
-    pub fn new(dimensions: UVec2, windowsize: Vec2) -> GameBoard {
-        let origin = find_origin(windowsize);
+    pub fn new(config: &GameConfig, windowsize: Vec2) -> GameBoard {
+        let dimensions = config.dimensions();
+        let origin = find_origin(windowsize, dimensions);
 
         Self {
-            dimensions,
-            forward: vec![None; (dimensions.x * dimensions.y) as usize],
+            board: Board::new(dimensions, config.min_match_length, config.match_rule),
             backward: HashMap::new(),
+            obstacle_sprites: HashMap::new(),
             origin,
             entity: Entity::PLACEHOLDER,
-        }
-    }
-
-    pub fn default() -> GameBoard {
-        let origin = find_origin(Vec2::new(1280., 720.));
-
-        Self {
-            dimensions: (8, 8).into(),
-            forward: vec![Some(tile::TileDesc::new()); (BOARD_HEIGHT * BOARD_WIDTH) as usize],
-            backward: HashMap::new(),
-            origin: origin,
-            entity: Entity::PLACEHOLDER,
+            cascade: 0,
+            swap_focus: Vec::new(),
+            triggered: HashSet::new(),
+            swap_duration: config.swap_duration,
+            fall_duration: config.fall_duration,
+            clear_duration: config.clear_duration,
         }
     }
 
@@ -105,47 +177,25 @@ impl GameBoard {
             return None;
         }
         let grid_pos = UVec2::new((grid_x / TILE_WIDTH) as u32, (grid_y / TILE_HEIGHT) as u32);
-        Some(self.idx(grid_pos))
+        let index = self.idx(grid_pos);
+        if self.is_hole(index) {
+            return None;
+        }
+        Some(index)
     }
 
-    pub fn find_grid_from_index(&self, index: usize) -> UVec2 {
-        UVec2::new(
-            (index % self.dimensions.x as usize) as u32,
-            (index / self.dimensions.y as usize) as u32,
-        )
+    pub fn find_world_from_grid(&self, grid_pos: UVec2) -> Vec2 {
+        self.get_offsets() + self.find_local_from_grid(grid_pos)
     }
 
     pub fn find_grid_from_world(&self, position: Vec2) -> Option<UVec2> {
-        if let Some(index) = self.find_index_from_world(position) {
-            Some(self.find_grid_from_index(index))
-        } else {
-            None
-        }
-    }
-
-    pub fn get_tile(&self, grid_pos: UVec2) -> Option<TileDesc> {
-        self.forward[grid_pos.y as usize * self.dimensions.x as usize + grid_pos.x as usize]
+        self.find_index_from_world(position)
+            .map(|index| self.find_grid_from_index(index))
     }
 
     pub fn get_entity(&mut self, grid_pos: UVec2) -> Option<Entity> {
-        self.backward
-            .get(&(grid_pos.y as usize * self.dimensions.x as usize + grid_pos.x as usize))
-            .copied()
-    }
-
-    pub fn get_board_pos(&self, grid_pos: UVec2) -> Vec2 {
-        Vec2::new(
-            (grid_pos.x as f32 * TILE_WIDTH) + (TILE_WIDTH / 2.),
-            (grid_pos.y as f32 * TILE_WIDTH) + (TILE_WIDTH / 2.),
-        )
-    }
-
-    pub fn get_world_pos(&self, grid_pos: UVec2) -> Vec2 {
-        let offsets = self.get_offsets();
-        Vec2::new(
-            grid_pos.x as f32 * TILE_WIDTH + offsets.x,
-            -(grid_pos.y as f32 * TILE_HEIGHT + offsets.y),
-        )
+        let index = self.idx(grid_pos);
+        self.backward.get(&index).copied()
     }
 
     pub fn get_offsets(&self) -> Vec2 {
@@ -154,206 +204,167 @@ impl GameBoard {
         Vec2::new(x_offset, y_offset)
     }
 
-    pub fn resolve_horizontal_matches(&mut self, to_be_deleted: &mut HashSet<usize>) {
-        for y in 0..self.dimensions.y {
-            let mut match_counter: u32 = 1;
-            let mut color_to_match = self.forward[self.idx((0, y))].unwrap().color;
-
-            for x in 1..self.dimensions.x {
-                let next_entity_color = self.forward[self.idx((x, y))].unwrap().color;
-                if next_entity_color == color_to_match {
-                    match_counter += 1;
-                } else {
-                    color_to_match = next_entity_color;
-
-                    if match_counter >= MIN_MATCH_LENGTH {
-                        let first_match = x - match_counter;
-                        for backtrace in first_match..x {
-                            let grid_index = self.idx((backtrace, y));
-                            to_be_deleted.insert(grid_index);
-
-                            info!("Pushed tile to be deleted at {}, {}", backtrace, y);
-                        }
-                    }
-                    match_counter = 1;
-
-                    if x >= self.dimensions.x - 2 {
-                        break;
-                    }
-                }
-            }
-            if match_counter >= MIN_MATCH_LENGTH {
-                let first_match = self.dimensions.x - match_counter;
-                for backtrace in first_match..self.dimensions.x {
-                    let grid_index = self.idx((backtrace, y));
-                    to_be_deleted.insert(grid_index);
-
-                    info!("Pushed tile to be deleted at {}, {}", backtrace, y);
-                }
-            }
-        }
+    pub fn swap_tiles(&mut self, commands: &mut Commands, first: UVec2, second: UVec2) {
+        let first_index = self.idx(first);
+        let second_index = self.idx(second);
+        let first_entity = self.backward.remove(&first_index).unwrap();
+        let second_entity = self.backward.remove(&second_index).unwrap();
+
+        self.swap(first_index, second_index);
+        self.backward.insert(second_index, first_entity);
+        self.backward.insert(first_index, second_entity);
+
+        let first_transform = self.find_local_from_grid(first);
+        let second_transform = self.find_local_from_grid(second);
+
+        commands
+            .entity(first_entity)
+            .insert(TilePosition(second))
+            .insert(TileMoving {
+                origin: first_transform,
+                destination: second_transform,
+                duration: Timer::from_seconds(self.swap_duration, TimerMode::Once),
+            });
+        commands
+            .entity(second_entity)
+            .insert(TilePosition(first))
+            .insert(TileMoving {
+                origin: second_transform,
+                destination: first_transform,
+                duration: Timer::from_seconds(self.swap_duration, TimerMode::Once),
+            });
     }
 
-    pub fn resolve_vertical_matches(&mut self, to_be_deleted: &mut HashSet<usize>) {
-        for x in 0..self.dimensions.x {
-            let mut match_counter: u32 = 1;
-            let mut color_to_match = self.forward[self.idx((x, 0))].unwrap().color;
-
-            for y in 1..self.dimensions.y {
-                let next_entity_color = self.forward[self.idx((x, y))].unwrap().color;
-                if next_entity_color == color_to_match {
-                    match_counter += 1;
-                } else {
-                    color_to_match = next_entity_color;
-
-                    if match_counter >= MIN_MATCH_LENGTH {
-                        let first_match = y - match_counter;
-                        for backtrace in first_match..y {
-                            let grid_index = self.idx((x, backtrace));
-                            to_be_deleted.insert(grid_index);
-
-                            info!("Pushed tile to be deleted at {}, {}", x, backtrace);
-                        }
-                    }
-                    match_counter = 1;
+    /// Takes the tiles out of the board and leaves them to shrink away.
+    pub fn clear_tiles(&mut self, commands: &mut Commands, removed: &[usize]) {
+        for index in removed {
+            let entity = self.backward.remove(index).unwrap();
 
-                    if y >= self.dimensions.y - 2 {
-                        break;
-                    }
-                }
-            }
-            if match_counter >= MIN_MATCH_LENGTH {
-                let first_match = self.dimensions.y - match_counter;
-                for backtrace in first_match..self.dimensions.y {
-                    let grid_index = self.idx((x, backtrace));
-                    to_be_deleted.insert(grid_index);
+            commands
+                .entity(entity)
+                .insert(TileClearing(Timer::from_seconds(
+                    self.clear_duration,
+                    TimerMode::Once,
+                )));
 
-                    info!("Pushed tile to be deleted at {}, {}", x, backtrace);
-                }
-            }
+            info!("Clearing: {:?}", entity);
         }
     }
 
-    pub fn remove_matches(&mut self, commands: &mut Commands, to_be_deleted: HashSet<usize>) {
-        for index in to_be_deleted {
-            self.forward[index] = None;
-            let entity = self.backward.remove(&index).unwrap();
-
-            commands.entity(entity).despawn();
-
-            info!("Depawned: {:?}", entity);
+    /// Lets the sprites of broken obstacles shrink away like cleared tiles.
+    pub fn clear_obstacles(&mut self, commands: &mut Commands, broken: &[usize]) {
+        for index in broken {
+            let Some(entity) = self.obstacle_sprites.remove(index) else {
+                continue;
+            };
+            commands
+                .entity(entity)
+                .insert(TileClearing(Timer::from_seconds(
+                    self.clear_duration,
+                    TimerMode::Once,
+                )));
+
+            info!("Broke the obstacle at {}", index);
         }
     }
 
-    pub fn shuffle_tiles_down(&mut self, commands: &mut Commands) -> Vec<u32> {
-        let mut column_spaces: Vec<u32> = Vec::new();
-        let mut space_in_row;
-        //let mut final_y = 0;
-        for x in 0..BOARD_WIDTH {
-            space_in_row = 0;
-            for y in 0..BOARD_HEIGHT {
-                let index = self.idx((x, y));
-
-                if self.forward[index].is_none() {
-                    for row in (y + 1)..BOARD_HEIGHT {
-                        let row_index = self.idx((x, row));
-                        if self.forward[row_index].is_some() {
-                            self.forward[index] = self.forward[row_index];
-                            self.forward[row_index] = None;
-                            let new_entity = self.backward.remove(&row_index).unwrap();
-                            self.backward.insert(index, new_entity);
-                            let origin_transform = self.find_local_from_grid((x, row).into());
-                            let destination_transform = self.find_local_from_grid((x, y).into());
-                            commands.entity(new_entity).insert(TileMoving {
-                                origin: origin_transform,
-                                destination: destination_transform,
-                                duration: Timer::from_seconds(0.5, TimerMode::Once),
-                            });
-
-                            info!("Moved tile from {}, {} to {}, {}", x, row, x, y);
-                            //final_y = y;
-                            //space_in_row = (BOARD_HEIGHT - 1) - final_y;
-                            break;
-                        }
-                    }
-                }
-            }
-            for y in 0..BOARD_HEIGHT {
-                let index = self.idx((x, y));
-                if self.forward[index].is_none() {
-                    space_in_row += 1;
-                }
-            }
-            column_spaces.push(space_in_row);
-
-            if space_in_row > 0 {
-                info!("Row {} found {} spaces", x, space_in_row);
-            }
+    pub fn upgrade_tiles(&mut self, commands: &mut Commands, upgraded: &[TileSpawn]) {
+        for upgrade in upgraded {
+            let entity = self.backward[&upgrade.index];
+            commands
+                .entity(entity)
+                .insert(upgrade.tile)
+                .with_children(|parent| spawn_special_marker(parent, upgrade.tile.special));
+
+            info!(
+                "Upgraded tile {} to {:?}",
+                upgrade.index, upgrade.tile.special
+            );
         }
+    }
 
-        column_spaces
+    pub fn move_tiles(&mut self, commands: &mut Commands, moves: &[TileMove], seconds: f32) {
+        // Lift every entity out first so moves can land on each other's
+        // starting cells, as they do during a reshuffle.
+        let entities: Vec<Entity> = moves
+            .iter()
+            .map(|tile_move| self.backward.remove(&tile_move.from).unwrap())
+            .collect();
+
+        for (tile_move, entity) in moves.iter().zip(entities) {
+            self.backward.insert(tile_move.to, entity);
+
+            let from = self.find_grid_from_index(tile_move.from);
+            let to = self.find_grid_from_index(tile_move.to);
+            commands
+                .entity(entity)
+                .insert(TilePosition(to))
+                .insert(TileMoving {
+                    origin: self.find_local_from_grid(from),
+                    destination: self.find_local_from_grid(to),
+                    duration: Timer::from_seconds(seconds, TimerMode::Once),
+                });
+
+            info!(
+                "Moved tile from {}, {} to {}, {}",
+                from.x, from.y, to.x, to.y
+            );
+        }
     }
 
-    pub fn spawn_new_tiles(
+    pub fn spawn_tiles(
         &mut self,
         commands: &mut Commands,
-        column_spaces: Vec<u32>,
-        game_assets: Res<GameAssets>,
+        spawns: &[TileSpawn],
+        game_assets: &GameAssets,
     ) {
-        for x in 0..BOARD_WIDTH as usize {
-            let num_spaces = *column_spaces.get(x).unwrap();
-            if num_spaces > 0 {
-                for y in 1..=num_spaces {
-                    let index = self.idx((x as u32, (BOARD_HEIGHT - y)));
-                    let tile_desc = TileDesc::new();
-                    self.forward[index] = Some(tile_desc);
-                    let destination =
-                        self.find_local_from_grid((x as u32, (BOARD_HEIGHT - y)).into());
-                    let origin = Vec2::new(
-                        destination.x,
-                        destination.y + (BOARD_HEIGHT as f32 * TILE_HEIGHT),
-                    );
-
-                    commands.entity(self.entity).with_children(|parent| {
-                        let tile_entity = parent
-                            .spawn(SpriteSheetBundle {
-                                atlas: TextureAtlas {
-                                    layout: game_assets.tiles_layout.clone(),
-                                    index: tile_desc.get_index(),
-                                },
-                                texture: game_assets.tiles.clone(),
-                                transform: Transform {
-                                    translation: Vec3::new(origin.x, origin.y, 2.0),
-                                    scale: Vec3::new(SPRITE_SCALE, SPRITE_SCALE, 1.0),
-                                    ..Default::default()
-                                },
-                                sprite: Sprite::default(),
-                                ..default()
-                            })
-                            .insert(Tile)
-                            .insert(tile_desc)
-                            .insert(TilePosition((x as u32, BOARD_HEIGHT - y).into()))
-                            .insert(TileMoving {
-                                origin,
-                                destination,
-                                duration: Timer::from_seconds(0.5, TimerMode::Once),
-                            })
-                            .id();
-                        self.backward.insert(index, tile_entity);
-
-                        info!("Spawned a tile at: {}, {}", x, BOARD_HEIGHT - y);
-                    });
-                }
-            }
+        for spawn in spawns {
+            let grid_pos = self.find_grid_from_index(spawn.index);
+            let destination = self.find_local_from_grid(grid_pos);
+            let origin = Vec2::new(
+                destination.x,
+                destination.y + (self.dimensions.y as f32 * TILE_HEIGHT),
+            );
+
+            commands.entity(self.entity).with_children(|parent| {
+                let tile_entity = parent
+                    .spawn(SpriteSheetBundle {
+                        atlas: TextureAtlas {
+                            layout: game_assets.tiles_layout.clone(),
+                            index: spawn.tile.get_index(),
+                        },
+                        texture: game_assets.tiles.clone(),
+                        transform: Transform {
+                            translation: Vec3::new(origin.x, origin.y, 2.0),
+                            scale: Vec3::new(SPRITE_SCALE, SPRITE_SCALE, 1.0),
+                            ..Default::default()
+                        },
+                        sprite: Sprite::default(),
+                        ..default()
+                    })
+                    .insert(Tile)
+                    .insert(spawn.tile)
+                    .insert(TilePosition(grid_pos))
+                    .insert(TileMoving {
+                        origin,
+                        destination,
+                        duration: Timer::from_seconds(self.fall_duration, TimerMode::Once),
+                    })
+                    .with_children(|tile| spawn_special_marker(tile, spawn.tile.special))
+                    .id();
+                self.backward.insert(spawn.index, tile_entity);
+
+                info!("Spawned a tile at: {}, {}", grid_pos.x, grid_pos.y);
+            });
         }
     }
 }
 
-pub fn find_origin(windowsize: Vec2) -> Vec2 {
+pub fn find_origin(windowsize: Vec2, dimensions: UVec2) -> Vec2 {
     let window_height = windowsize.y;
     let window_width = windowsize.x;
-    let board_height = BOARD_HEIGHT as f32 * TILE_HEIGHT;
-    let board_width = BOARD_WIDTH as f32 * TILE_WIDTH;
+    let board_height = dimensions.y as f32 * TILE_HEIGHT;
+    let board_width = dimensions.x as f32 * TILE_WIDTH;
 
     let top_margin = (window_height / 2.0) - (board_height / 2.0);
 
@@ -363,38 +374,121 @@ pub fn find_origin(windowsize: Vec2) -> Vec2 {
     Vec2::new(top_left_x, top_left_y)
 }
 
-pub fn create_gameboard(mut commands: Commands, window_query: Query<&Window, With<PrimaryWindow>>) {
-    let window = window_query.get_single().unwrap();
-    let window_size = Vec2::new(window.width(), window.height());
-    let dimensions = UVec2::new(BOARD_HEIGHT, BOARD_WIDTH);
-    let mut game_board = board::GameBoard::new(dimensions, window_size);
-
-    for y in 0..game_board.dimensions.y {
-        for x in 0..game_board.dimensions.x {
-            let index = game_board.idx((x, y));
-            let tile = TileDesc::new();
-            game_board.forward[index] = Some(tile);
-        }
+pub fn seed_game_rng(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    resume: Option<Res<ResumeGame>>,
+) {
+    if let Some(resume) = resume {
+        commands.insert_resource(resume.0.game_rng());
+        info!("Resumed the board from seed {}", resume.0.seed);
+        return;
     }
+    let seed = config.seed.unwrap_or_else(|| thread_rng().gen());
+    commands.insert_resource(GameRng::new(seed));
+
+    info!("Seeded the board with {}", seed);
+}
 
-    check_intial_tiles(&mut game_board);
+pub fn create_gameboard(
+    mut commands: Commands,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    config: Res<GameConfig>,
+    mut rng: ResMut<GameRng>,
+    resume: Option<Res<ResumeGame>>,
+    layout: Option<Res<LevelLayout>>,
+    spawner: Res<TileSpawner>,
+) {
+    // Without a window, e.g. when running headless, lay the board out for the
+    // default window size.
+    let window_size = window_query
+        .get_single()
+        .map(|window| Vec2::new(window.width(), window.height()))
+        .unwrap_or(Vec2::new(1280., 720.));
+    let mut game_board = board::GameBoard::new(&config, window_size);
+    match resume {
+        Some(resume) if resume.0.tiles.len() == game_board.forward.len() => {
+            game_board.forward.clone_from(&resume.0.tiles);
+            for index in resume.0.holes.iter() {
+                game_board.add_hole(*index);
+            }
+            if resume.0.obstacles.len() == game_board.obstacles.len() {
+                game_board.obstacles.clone_from(&resume.0.obstacles);
+            }
+        }
+        _ => {
+            spawner.0.deal(&mut game_board, config.colors, &mut rng.rng);
+            let fixed = match layout {
+                Some(layout) => apply_layout(&mut game_board, &layout.0),
+                None => HashSet::new(),
+            };
+            check_intial_tiles(
+                &mut game_board,
+                &*spawner.0,
+                &mut rng,
+                config.colors,
+                &fixed,
+            );
+        }
+    }
 
     commands.insert_resource(game_board);
     info!("Inserted Gameboard");
 }
 
-pub fn check_intial_tiles(game_board: &mut GameBoard) {
-    let mut to_be_deleted: HashSet<usize> = HashSet::new();
-    game_board.resolve_horizontal_matches(&mut to_be_deleted);
-    game_board.resolve_vertical_matches(&mut to_be_deleted);
-    if to_be_deleted.len() == 0 {
-        return;
+/// Places a level's fixed tiles and obstacles over the random fill and cuts
+/// out its holes.
+/// Returns where the fixed tiles are, so they aren't replaced.
+pub fn apply_layout(game_board: &mut GameBoard, layout: &[Cell]) -> HashSet<usize> {
+    let mut fixed = HashSet::new();
+    for (index, cell) in layout.iter().enumerate().take(game_board.forward.len()) {
+        match cell {
+            Cell::Random => {}
+            Cell::Fixed(color) => {
+                game_board.forward[index] = Some(TileDesc {
+                    color: *color,
+                    mark: TileMarking::Blank,
+                    special: TileSpecial::None,
+                });
+                fixed.insert(index);
+            }
+            Cell::Hole => game_board.add_hole(index),
+            Cell::Obstacle(obstacle) => game_board.add_obstacle(index, *obstacle),
+            // Spawners only mark the columns ingredients drop into, see
+            // LevelProgress.
+            Cell::Spawner => {}
+        }
     }
-    for index in to_be_deleted.iter() {
-        game_board.forward[*index] = Some(TileDesc::new());
-        info!("Replaced already matching tiles.")
+    fixed
+}
+
+/// Rerolls tiles with the spawn strategy until nothing matches. Fixed tiles
+/// are left alone, so a level can start with a match on purpose.
+pub fn check_intial_tiles(
+    game_board: &mut GameBoard,
+    spawner: &dyn SpawnStrategy,
+    rng: &mut GameRng,
+    colors: u32,
+    fixed: &HashSet<usize>,
+) {
+    for _ in 0..MAX_DEAL_REROLLS {
+        let mut to_be_deleted: Vec<usize> = game_board
+            .find_matches()
+            .into_iter()
+            .filter(|index| !fixed.contains(index))
+            .collect();
+        if to_be_deleted.is_empty() {
+            return;
+        }
+        // Sorted so the same seed always replaces the same tiles in the same order.
+        to_be_deleted.sort_unstable();
+        for index in to_be_deleted.iter() {
+            let tile = spawner.pick(game_board, *index, colors, &mut rng.rng);
+            game_board.forward[*index] = Some(tile);
+            info!("Replaced already matching tiles.")
+        }
     }
-    check_intial_tiles(game_board);
+    warn!("The deal still has matches after {MAX_DEAL_REROLLS} rerolls");
 }
 
 pub fn fill_gameboard(
@@ -415,24 +509,28 @@ pub fn fill_gameboard(
             ..Default::default()
         })
         .with_children(|parent| {
-            parent
-                .spawn(SpriteBundle {
-                    sprite: Sprite {
-                        color: Color::rgb(0.0, 0.0, 0.0),
-                        custom_size: Some(Vec2::new(
-                            TILE_WIDTH * BOARD_WIDTH as f32 + (2.0 * BORDER_SIZE),
-                            TILE_HEIGHT * BOARD_HEIGHT as f32 + (2.0 * BORDER_SIZE),
-                        )),
+            // One square per cell, each a border wider than the tile, so the
+            // background follows the outline of boards with holes in them.
+            for index in 0..game_board.forward.len() {
+                if game_board.is_hole(index) {
+                    continue;
+                }
+                let local = game_board.find_local_from_grid(game_board.find_grid_from_index(index));
+                parent
+                    .spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::rgb(0.0, 0.0, 0.0),
+                            custom_size: Some(Vec2::new(
+                                TILE_WIDTH + (2.0 * BORDER_SIZE),
+                                TILE_HEIGHT + (2.0 * BORDER_SIZE),
+                            )),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(local.extend(1.5)),
                         ..default()
-                    },
-                    transform: Transform::from_translation(Vec3::new(
-                        (BOARD_WIDTH / 2) as f32 * TILE_WIDTH,
-                        (BOARD_HEIGHT / 2) as f32 * TILE_HEIGHT,
-                        1.5,
-                    )),
-                    ..default()
-                })
-                .insert(BlackBackground);
+                    })
+                    .insert(BlackBackground);
+            }
         })
         .with_children(|parent| {
             let tile_width = TILE_WIDTH;
@@ -442,7 +540,15 @@ pub fn fill_gameboard(
                 for x in 0..game_board.dimensions.x {
                     let grid_pos = (x, y).into();
                     let index = game_board.idx(grid_pos);
-                    let tile_desc = game_board.forward[index].unwrap();
+                    let local = game_board.find_local_from_grid(grid_pos);
+                    if let Some(entity) = spawn_obstacle(parent, local, game_board.obstacles[index])
+                    {
+                        game_board.obstacle_sprites.insert(index, entity);
+                    }
+                    // A game saved mid cascade still has holes to refill.
+                    let Some(tile_desc) = game_board.forward[index] else {
+                        continue;
+                    };
                     let tile_entity = parent
                         .spawn(SpriteSheetBundle {
                             atlas: TextureAtlas {
@@ -465,6 +571,7 @@ pub fn fill_gameboard(
                         .insert(Tile)
                         .insert(tile_desc)
                         .insert(TilePosition(grid_pos))
+                        .with_children(|tile| spawn_special_marker(tile, tile_desc.special))
                         .id();
                     game_board.backward.insert(index, tile_entity);
                 }
@@ -474,15 +581,227 @@ pub fn fill_gameboard(
     game_board.entity = board_entity;
 }
 
-pub fn match_remove_refill(
+pub fn teardown_gameboard(mut commands: Commands, game_board: Option<Res<GameBoard>>) {
+    if let Some(game_board) = game_board {
+        commands.entity(game_board.entity).despawn_recursive();
+    }
+    commands.remove_resource::<GameBoard>();
+    commands.remove_resource::<SelectedTile>();
+    commands.remove_resource::<PendingSwap>();
+
+    info!("Removed Gameboard");
+}
+
+pub fn swap_processor(
+    mut commands: Commands,
+    mut swap_request: EventReader<SwapRequestEvent>,
+    mut game_board: ResMut<GameBoard>,
+    mut next_state: ResMut<NextState<ResolveState>>,
+) {
+    // Only the first request counts, the rest arrive while it is still pending.
+    let Some(request) = swap_request.read().next().copied() else {
+        return;
+    };
+    swap_request.clear();
+
+    if request.first.ldistance(request.second) != 1
+        || !game_board.can_swap(request.first, request.second)
+    {
+        info!(
+            "Refused swap of {}, {} and {}, {}",
+            request.first.x, request.first.y, request.second.x, request.second.y
+        );
+        return;
+    }
+    game_board.swap_tiles(&mut commands, request.first, request.second);
+    commands.insert_resource(PendingSwap {
+        first: request.first,
+        second: request.second,
+    });
+    next_state.set(ResolveState::Swapping);
+
+    info!(
+        "Swapsies! {}, {} and {}, {}",
+        request.first.x, request.first.y, request.second.x, request.second.y
+    );
+}
+
+/// Once the swap has finished animating it is either kept, if it made a
+/// match, or swapped back. The board goes idle after swapping back.
+pub fn finish_swap(
+    mut commands: Commands,
+    mut game_board: ResMut<GameBoard>,
+    pending_swap: Option<Res<PendingSwap>>,
+    moving_query: Query<(), With<TileMoving>>,
+    mut next_state: ResMut<NextState<ResolveState>>,
+) {
+    if !moving_query.is_empty() {
+        return;
+    }
+    let Some(pending_swap) = pending_swap else {
+        next_state.set(ResolveState::Idle);
+        return;
+    };
+    commands.remove_resource::<PendingSwap>();
+
+    let first = game_board.idx(pending_swap.first);
+    let second = game_board.idx(pending_swap.second);
+    let triggered = game_board.swap_activation(first, second);
+    if !triggered.is_empty() || !game_board.find_matches().is_empty() {
+        game_board.cascade = 0;
+        game_board.swap_focus = vec![first, second];
+        game_board.triggered = triggered;
+        next_state.set(ResolveState::Matching);
+        return;
+    }
+    game_board.swap_tiles(&mut commands, pending_swap.first, pending_swap.second);
+
+    info!(
+        "No match, swapping back {}, {} and {}, {}",
+        pending_swap.first.x, pending_swap.first.y, pending_swap.second.x, pending_swap.second.y
+    );
+}
+
+/// Every game starts by dropping and refilling, which is a no-op for a fresh
+/// board but fills any holes in a resumed one.
+pub fn start_resolving(mut next_state: ResMut<NextState<ResolveState>>) {
+    next_state.set(ResolveState::Falling);
+}
+
+pub fn stop_resolving(mut next_state: ResMut<NextState<ResolveState>>) {
+    next_state.set(ResolveState::Idle);
+}
+
+pub fn announce_resolve_phase(
+    resolve_state: Res<State<ResolveState>>,
+    mut phase_events: EventWriter<ResolvePhaseEvent>,
+) {
+    let phase = resolve_state.get().clone();
+    info!("Resolve phase: {:?}", phase);
+    phase_events.send(ResolvePhaseEvent { phase });
+}
+
+pub fn settle_board(mut settled: EventWriter<BoardSettledEvent>) {
+    settled.send(BoardSettledEvent);
+}
+
+type BusyTiles<'w, 's> = Query<'w, 's, (), Or<(With<TileMoving>, With<TileClearing>)>>;
+
+/// Moves on to the next phase once every tile has finished moving and
+/// clearing.
+pub fn advance_when_still(
+    next: ResolveState,
+) -> impl FnMut(BusyTiles, ResMut<NextState<ResolveState>>) {
+    move |busy_query, mut next_state| {
+        if busy_query.is_empty() {
+            next_state.set(next.clone());
+        }
+    }
+}
+
+/// Once the new tiles have landed, goes back to falling if any of them can
+/// slide on into a capped column, otherwise looks for matches.
+pub fn finish_refilling(
+    busy_query: BusyTiles,
+    game_board: Res<GameBoard>,
+    mut next_state: ResMut<NextState<ResolveState>>,
+) {
+    if !busy_query.is_empty() {
+        return;
+    }
+    next_state.set(if game_board.can_fall() {
+        ResolveState::Falling
+    } else {
+        ResolveState::Matching
+    });
+}
+
+pub fn find_board_matches(
+    mut commands: Commands,
+    mut game_board: ResMut<GameBoard>,
+    config: Res<GameConfig>,
+    mut rng: ResMut<GameRng>,
+    mut matched: EventWriter<TilesMatchedEvent>,
+    mut next_state: ResMut<NextState<ResolveState>>,
+) {
+    let focus = std::mem::take(&mut game_board.swap_focus);
+    let triggered = std::mem::take(&mut game_board.triggered);
+    let resolution = game_board.clear_matches(&focus, triggered);
+    if resolution.is_empty() {
+        // A reshuffle animates like a fall, so it waits the same way.
+        let reshuffled = reshuffle_if_deadlocked(
+            &mut commands,
+            &mut game_board,
+            &mut rng,
+            config.shuffle_duration,
+        );
+        next_state.set(if reshuffled {
+            ResolveState::Falling
+        } else {
+            ResolveState::Idle
+        });
+        return;
+    }
+    game_board.cascade += 1;
+    game_board.clear_tiles(&mut commands, &resolution.removed);
+    game_board.clear_obstacles(&mut commands, &resolution.broken);
+    game_board.clear_tiles(&mut commands, &resolution.collected);
+    game_board.upgrade_tiles(&mut commands, &resolution.upgraded);
+    next_state.set(ResolveState::Clearing);
+
+    matched.send(TilesMatchedEvent {
+        runs: resolution.runs,
+        removed: resolution.removed,
+        cleared: resolution.cleared,
+        collected: resolution.collected,
+        cascade: game_board.cascade,
+    });
+}
+
+pub fn drop_tiles(mut commands: Commands, mut game_board: ResMut<GameBoard>) {
+    let falls = game_board.apply_gravity();
+    let fall_duration = game_board.fall_duration;
+    game_board.move_tiles(&mut commands, &falls, fall_duration);
+}
+
+pub fn refill_board(
     mut commands: Commands,
     mut game_board: ResMut<GameBoard>,
     game_assets: Res<GameAssets>,
+    config: Res<GameConfig>,
+    mut rng: ResMut<GameRng>,
+    progress: Option<ResMut<LevelProgress>>,
+    spawner: Res<TileSpawner>,
 ) {
-    let mut to_be_deleted: HashSet<usize> = HashSet::new();
-    game_board.resolve_horizontal_matches(&mut to_be_deleted);
-    game_board.resolve_vertical_matches(&mut to_be_deleted);
-    game_board.remove_matches(&mut commands, to_be_deleted);
-    let column_spaces = game_board.shuffle_tiles_down(&mut commands);
-    game_board.spawn_new_tiles(&mut commands, column_spaces, game_assets)
+    // The ingredient goes in first, so the spawn strategy fills around it.
+    let ingredient = progress.and_then(|mut progress| progress.drop_ingredient(&mut game_board));
+    let mut spawns = spawner
+        .0
+        .refill(&mut game_board, config.colors, &mut rng.rng);
+    spawns.extend(ingredient);
+    game_board.spawn_tiles(&mut commands, &spawns, &game_assets);
+}
+
+/// Shuffles the board if there are no moves left. Returns whether it did.
+pub fn reshuffle_if_deadlocked(
+    commands: &mut Commands,
+    game_board: &mut GameBoard,
+    rng: &mut GameRng,
+    shuffle_duration: f32,
+) -> bool {
+    if !game_board.find_valid_moves().is_empty() {
+        return false;
+    }
+    info!("No valid moves left, reshuffling");
+
+    match game_board.shuffle(&mut rng.rng) {
+        Some(moves) => {
+            game_board.move_tiles(commands, &moves, shuffle_duration);
+            true
+        }
+        None => {
+            warn!("Could not find a playable layout to reshuffle into");
+            false
+        }
+    }
 }
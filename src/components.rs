@@ -24,6 +24,25 @@ pub struct TileMoving {
     pub duration: Timer,
 }
 
+/// A tile that has been matched and is shrinking away before it despawns.
+#[derive(Component)]
+pub struct TileClearing(pub Timer);
+
+/// A speck thrown out when a tile clears. It drifts and fades until its
+/// timer runs out.
+#[derive(Component)]
+pub struct Particle {
+    pub velocity: Vec2,
+    pub lifetime: Timer,
+}
+
+#[derive(Component)]
+pub struct SpecialMarker;
+
+/// The sprite of a cell's ice, chain or stone.
+#[derive(Component)]
+pub struct ObstacleSprite;
+
 #[derive(Component)]
 pub struct TileHighlight(pub Timer);
 
@@ -49,9 +68,7 @@ impl Add for TilePosition {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Self {
-            0: (self.0.x + rhs.0.x, self.0.y + rhs.0.y).into(),
-        }
+        Self((self.0.x + rhs.0.x, self.0.y + rhs.0.y).into())
     }
 }
 
@@ -59,13 +76,13 @@ impl Sub for TilePosition {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Self {
-            0: (
+        Self(
+            (
                 self.0.x.saturating_sub(rhs.0.x),
                 self.0.y.saturating_sub(rhs.0.y),
             )
                 .into(),
-        }
+        )
     }
 }
 
@@ -75,6 +92,6 @@ impl Add<(i8, i8)> for TilePosition {
     fn add(self, (x, y): (i8, i8)) -> Self::Output {
         let x = ((self.0.x as i32) + x as i32) as u32;
         let y = ((self.0.y as i32) + y as i32) as u32;
-        Self { 0: (x, y).into() }
+        Self((x, y).into())
     }
 }
@@ -0,0 +1,257 @@
+use crate::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const CONFIG_FILE: &str = "config.ron";
+const MAX_COLORS: u32 = 18;
+
+/// What the player is playing, picked from the main menu.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum GameMode {
+    /// The levels in assets/levels, one after another.
+    #[default]
+    Levels,
+    /// Against the clock, see ArcadePlugin.
+    Arcade,
+    /// Endless, with no way to lose, see ZenPlugin.
+    Zen,
+}
+
+/// Board and timing settings, read from config.ron and overridable on the
+/// command line, e.g. `--width 10 --height 7 --colors 6 --min-match 3`.
+/// Without a seed every game is dealt from a fresh random one.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct GameConfig {
+    pub board_width: u32,
+    pub board_height: u32,
+    pub colors: u32,
+    pub min_match_length: u32,
+    pub match_rule: MatchRule,
+    pub spawn_rule: SpawnRule,
+    pub swap_duration: f32,
+    pub fall_duration: f32,
+    pub clear_duration: f32,
+    pub shuffle_duration: f32,
+    pub seed: Option<u64>,
+    pub mode: GameMode,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            board_width: BOARD_WIDTH,
+            board_height: BOARD_HEIGHT,
+            colors: 17,
+            min_match_length: MIN_MATCH_LENGTH,
+            match_rule: MATCH_RULE,
+            spawn_rule: SpawnRule::Uniform,
+            swap_duration: 0.5,
+            fall_duration: 0.5,
+            clear_duration: 0.3,
+            shuffle_duration: 0.8,
+            seed: None,
+            mode: GameMode::Levels,
+        }
+    }
+}
+
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        // A config inserted up front, e.g. by a test, wins over the command line.
+        if !app.world.contains_resource::<GameConfig>() {
+            app.insert_resource(GameConfig::load(std::env::args().skip(1).collect()));
+        }
+        let base = app.world.resource::<GameConfig>().clone();
+        app.insert_resource(BaseConfig(base));
+    }
+}
+
+/// The config as it was loaded, before a level or game mode changed it.
+/// Every game picked from the main menu starts over from it.
+#[derive(Resource, Clone, Debug)]
+pub struct BaseConfig(pub GameConfig);
+
+impl BaseConfig {
+    pub fn with_mode(&self, mode: GameMode) -> GameConfig {
+        GameConfig {
+            mode,
+            ..self.0.clone()
+        }
+    }
+}
+
+impl GameConfig {
+    /// Reads the config file, `--config <path>` or config.ron by default, and
+    /// then applies any overrides given on the command line.
+    pub fn load(args: Vec<String>) -> GameConfig {
+        let path = args
+            .iter()
+            .position(|arg| arg == "--config")
+            .and_then(|position| args.get(position + 1))
+            .map(String::as_str)
+            .unwrap_or(CONFIG_FILE);
+
+        let mut config = GameConfig::from_file(Path::new(path));
+        config.apply_args(&args);
+        config.clamp();
+
+        info!("Loaded config: {:?}", config);
+        config
+    }
+
+    pub fn from_file(path: &Path) -> GameConfig {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            info!("No config file at {}, using defaults", path.display());
+            return GameConfig::default();
+        };
+        match ron::from_str(&contents) {
+            Ok(config) => config,
+            Err(error) => {
+                warn!("Could not parse {}: {}", path.display(), error);
+                GameConfig::default()
+            }
+        }
+    }
+
+    pub fn apply_args(&mut self, args: &[String]) {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            // Handled by the replay plugin, and the only flag without a value.
+            if arg == "--verify" {
+                continue;
+            }
+            let value = args.next();
+            let parsed = value.and_then(|value| value.parse::<u32>().ok());
+
+            match (arg.as_str(), parsed) {
+                ("--config" | "--record" | "--replay", _) => {}
+                ("--width", Some(width)) => self.board_width = width,
+                ("--height", Some(height)) => self.board_height = height,
+                ("--colors", Some(colors)) => self.colors = colors,
+                ("--min-match", Some(length)) => self.min_match_length = length,
+                ("--seed", _) => match value.and_then(|value| value.parse::<u64>().ok()) {
+                    Some(seed) => self.seed = Some(seed),
+                    None => warn!("--seed expects a number"),
+                },
+                ("--match-rule", _) => match value.map(String::as_str) {
+                    Some("color") => self.match_rule = MatchRule::Color,
+                    Some("mark") => self.match_rule = MatchRule::Mark,
+                    Some("both") => self.match_rule = MatchRule::ColorAndMark,
+                    _ => warn!("--match-rule expects color, mark or both"),
+                },
+                ("--spawn", _) => match value.map(String::as_str) {
+                    Some("uniform") => self.spawn_rule = SpawnRule::Uniform,
+                    Some("weighted") => self.spawn_rule = SpawnRule::Weighted,
+                    Some("avoid-matches") => self.spawn_rule = SpawnRule::AvoidMatches,
+                    Some("guarantee-move") => self.spawn_rule = SpawnRule::GuaranteeMove,
+                    _ => {
+                        warn!("--spawn expects uniform, weighted, avoid-matches or guarantee-move")
+                    }
+                },
+                _ => warn!("Ignoring argument {} {:?}", arg, value),
+            }
+        }
+    }
+
+    pub fn clamp(&mut self) {
+        self.min_match_length = self.min_match_length.max(2);
+        self.board_width = self.board_width.max(self.min_match_length);
+        self.board_height = self.board_height.max(self.min_match_length);
+        self.colors = self.colors.clamp(2, MAX_COLORS);
+    }
+
+    pub fn dimensions(&self) -> UVec2 {
+        UVec2::new(self.board_width, self.board_height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_args(args: &[&str]) -> GameConfig {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut config = GameConfig::default();
+        config.apply_args(&args);
+        config
+    }
+
+    #[test]
+    fn command_line_overrides_the_config() {
+        let config = with_args(&[
+            "--width",
+            "10",
+            "--height",
+            "7",
+            "--colors",
+            "6",
+            "--min-match",
+            "4",
+            "--seed",
+            "12345678901",
+            "--match-rule",
+            "both",
+            "--spawn",
+            "guarantee-move",
+        ]);
+        assert_eq!(
+            config,
+            GameConfig {
+                board_width: 10,
+                board_height: 7,
+                colors: 6,
+                min_match_length: 4,
+                match_rule: MatchRule::ColorAndMark,
+                spawn_rule: SpawnRule::GuaranteeMove,
+                seed: Some(12345678901),
+                ..default()
+            }
+        );
+
+        assert_eq!(
+            with_args(&["--match-rule", "mark"]).match_rule,
+            MatchRule::Mark
+        );
+        assert_eq!(
+            with_args(&["--spawn", "weighted"]).spawn_rule,
+            SpawnRule::Weighted
+        );
+        assert_eq!(
+            with_args(&["--spawn", "avoid-matches"]).spawn_rule,
+            SpawnRule::AvoidMatches
+        );
+    }
+
+    #[test]
+    fn bad_and_unknown_arguments_are_ignored() {
+        let config = with_args(&[
+            "--replay", "game.ron", "--width", "wide", "--spawn", "sideways", "--seed", "-1",
+            "--bogus", "1",
+        ]);
+        assert_eq!(config, GameConfig::default());
+    }
+
+    #[test]
+    fn verify_takes_no_value() {
+        let config = with_args(&["--replay", "game.ron", "--verify", "--colors", "5"]);
+        assert_eq!(config.colors, 5);
+    }
+
+    #[test]
+    fn clamp_keeps_the_board_playable() {
+        let mut config = with_args(&["--colors", "1", "--min-match", "0", "--width", "1"]);
+        config.clamp();
+        assert_eq!(config.colors, 2);
+        assert_eq!(config.min_match_length, 2);
+        assert_eq!(config.board_width, 2);
+
+        let mut config = with_args(&["--colors", "40", "--min-match", "5", "--height", "3"]);
+        config.clamp();
+        assert_eq!(config.colors, MAX_COLORS);
+        assert_eq!(config.board_height, 5);
+    }
+}
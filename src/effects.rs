@@ -2,15 +2,108 @@ use crate::*;
 use bevy::prelude::*;
 use simple_easing::{expo_in, expo_out, reverse};
 
+const PARTICLES_PER_TILE: usize = 6;
+const PARTICLE_SIZE: f32 = 6.0;
+const PARTICLE_SPEED: f32 = 150.0;
+const PARTICLE_LIFETIME: f32 = 0.5;
+
 pub struct EffectsPlugin;
 
 impl Plugin for EffectsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, add_sprite_to_selected_tile);
-        app.add_systems(Update, animated_selected_tile);
+        app.add_systems(
+            Update,
+            (
+                add_sprite_to_selected_tile,
+                animated_selected_tile,
+                spawn_clear_particles,
+                animate_clearing_tiles,
+                animate_particles,
+            )
+                .run_if(in_state(GameState::InGame)),
+        );
     }
 }
 
+pub fn spawn_special_marker(parent: &mut ChildBuilder, special: TileSpecial) {
+    let (color, size, rotation) = match special {
+        TileSpecial::None => return,
+        TileSpecial::RowClearer => (Color::rgba(1.0, 1.0, 1.0, 0.85), Vec2::new(28.0, 6.0), 0.0),
+        TileSpecial::ColumnClearer => (Color::rgba(1.0, 1.0, 1.0, 0.85), Vec2::new(6.0, 28.0), 0.0),
+        TileSpecial::Bomb => (Color::rgba(0.0, 0.0, 0.0, 0.7), Vec2::new(14.0, 14.0), 0.0),
+        TileSpecial::Ingredient => (Color::rgb(1.0, 0.85, 0.2), Vec2::new(20.0, 20.0), 0.0),
+        TileSpecial::ColorBomb => (
+            Color::rgba(1.0, 1.0, 1.0, 0.9),
+            Vec2::new(14.0, 14.0),
+            std::f32::consts::FRAC_PI_4,
+        ),
+    };
+
+    parent
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, 1.0),
+                rotation: Quat::from_rotation_z(rotation),
+                ..Default::default()
+            },
+            ..default()
+        })
+        .insert(SpecialMarker);
+}
+
+/// Draws a cell's obstacle. The sprite is scaled like a tile so it shrinks
+/// away the same way when it breaks.
+pub fn spawn_obstacle(
+    parent: &mut ChildBuilder,
+    local: Vec2,
+    obstacle: Obstacle,
+) -> Option<Entity> {
+    let (color, size, rotation, z) = match obstacle {
+        Obstacle::None => return None,
+        Obstacle::Ice => (
+            Color::rgba(0.75, 0.9, 1.0, 0.7),
+            Vec2::new(TILE_WIDTH - 2.0, TILE_HEIGHT - 2.0),
+            0.0,
+            1.9,
+        ),
+        Obstacle::Chain => (
+            Color::rgba(0.3, 0.3, 0.35, 0.9),
+            Vec2::new(TILE_WIDTH, 8.0),
+            std::f32::consts::FRAC_PI_4,
+            2.5,
+        ),
+        Obstacle::Stone => (
+            Color::rgb(0.45, 0.42, 0.4),
+            Vec2::new(TILE_WIDTH - 6.0, TILE_HEIGHT - 6.0),
+            0.0,
+            2.0,
+        ),
+    };
+
+    let entity = parent
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(size / SPRITE_SCALE),
+                ..default()
+            },
+            transform: Transform {
+                translation: local.extend(z),
+                rotation: Quat::from_rotation_z(rotation),
+                scale: Vec3::new(SPRITE_SCALE, SPRITE_SCALE, 1.0),
+            },
+            ..default()
+        })
+        .insert(ObstacleSprite)
+        .id();
+    Some(entity)
+}
+
 pub fn add_sprite_to_selected_tile(
     mut commands: Commands,
     selected_tile: Option<ResMut<SelectedTile>>,
@@ -25,7 +118,9 @@ pub fn add_sprite_to_selected_tile(
                 }
                 let selected_pos = selected.as_mut().as_uvec2();
                 let selected_idx = game_board.idx(selected_pos);
-                let selected_entity = game_board.backward.get(&selected_idx).copied().unwrap();
+                let Some(selected_entity) = game_board.backward.get(&selected_idx).copied() else {
+                    return;
+                };
                 commands.entity(selected_entity).with_children(|parent| {
                     parent
                         .spawn(SpriteBundle {
@@ -75,3 +170,74 @@ pub fn animated_selected_tile(
         sprite.color.set_a(new_alpha);
     }
 }
+
+/// Throws out a burst of particles in the tile's colour as it starts to clear.
+pub fn spawn_clear_particles(
+    mut commands: Commands,
+    clear_query: Query<(&TileDesc, &Transform, &Parent), Added<TileClearing>>,
+) {
+    // Purely cosmetic, so this doesn't draw from the game's seeded RNG.
+    let mut rng = thread_rng();
+    for (tile, transform, parent) in clear_query.iter() {
+        let color = tile.color.to_color();
+        commands.entity(parent.get()).with_children(|parent| {
+            for _ in 0..PARTICLES_PER_TILE {
+                let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                let speed = PARTICLE_SPEED * rng.gen_range(0.5..1.0);
+                parent
+                    .spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(Vec2::splat(PARTICLE_SIZE)),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(
+                            transform.translation.truncate().extend(4.0),
+                        ),
+                        ..default()
+                    })
+                    .insert(Particle {
+                        velocity: Vec2::from_angle(angle) * speed,
+                        lifetime: Timer::from_seconds(PARTICLE_LIFETIME, TimerMode::Once),
+                    });
+            }
+        });
+    }
+}
+
+/// Shrinks and fades a cleared tile, then despawns it.
+pub fn animate_clearing_tiles(
+    mut commands: Commands,
+    mut clear_query: Query<(Entity, &mut Transform, &mut Sprite, &mut TileClearing)>,
+    time: Res<Time>,
+) {
+    for (entity, mut transform, mut sprite, mut clearing) in clear_query.iter_mut() {
+        clearing.0.tick(time.delta());
+        if clearing.0.finished() {
+            commands.entity(entity).despawn_recursive();
+            info!("Despawned: {:?}", entity);
+            continue;
+        }
+        let remaining = reverse(expo_in(clearing.0.fraction()));
+        transform.scale = Vec3::new(SPRITE_SCALE * remaining, SPRITE_SCALE * remaining, 1.0);
+        sprite.color.set_a(remaining);
+    }
+}
+
+pub fn animate_particles(
+    mut commands: Commands,
+    mut particle_query: Query<(Entity, &mut Transform, &mut Sprite, &mut Particle)>,
+    time: Res<Time>,
+) {
+    for (entity, mut transform, mut sprite, mut particle) in particle_query.iter_mut() {
+        particle.lifetime.tick(time.delta());
+        if particle.lifetime.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let slowdown = reverse(expo_out(particle.lifetime.fraction()));
+        let step = particle.velocity * slowdown * time.delta_seconds();
+        transform.translation += step.extend(0.0);
+        sprite.color.set_a(slowdown);
+    }
+}
@@ -0,0 +1,769 @@
+use crate::*;
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::{ButtonState, InputPlugin};
+use bevy::time::TimeUpdateStrategy;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+const FRAME_TIME: f32 = 1.0 / 30.0;
+const MAX_SETTLE_FRAMES: u32 = 1000;
+const SETTLED_FRAMES: u32 = 3;
+
+static NEXT_SAVE_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs the game headless with MinimalPlugins. Time advances by a fixed step
+/// every frame so animations always finish in the same number of updates.
+/// Each game saves to a temporary directory of its own, removed when the
+/// game is dropped.
+pub struct TestGame {
+    pub app: App,
+}
+
+impl TestGame {
+    pub fn new(config: GameConfig) -> TestGame {
+        TestGame::with(config, |_| {})
+    }
+
+    /// Like new, but lets the test add resources before the game starts.
+    pub fn with(config: GameConfig, setup: impl FnOnce(&mut App)) -> TestGame {
+        let save_dir = std::env::temp_dir().join(format!(
+            "match3-test-{}-{}",
+            std::process::id(),
+            NEXT_SAVE_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&save_dir).unwrap();
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(InputPlugin)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                FRAME_TIME,
+            )))
+            .insert_resource(config)
+            .insert_resource(SaveDir(save_dir))
+            .init_resource::<Levels>()
+            .insert_resource(GameAssets {
+                background: Handle::default(),
+                tiles: Handle::default(),
+                tiles_layout: Handle::default(),
+            })
+            .add_plugins(GamePlugin);
+        setup(&mut app);
+
+        let mut game = TestGame { app };
+        game.app.update();
+        game.app
+            .world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::InGame);
+        game.settle();
+        game
+    }
+
+    pub fn save_file(&self, mode: GameMode) -> std::path::PathBuf {
+        let save_dir = self.app.world.resource::<SaveDir>();
+        save_dir.save_file(mode).unwrap()
+    }
+
+    pub fn board(&self) -> &GameBoard {
+        self.app.world.resource::<GameBoard>()
+    }
+
+    pub fn score(&self) -> &Score {
+        self.app.world.resource::<Score>()
+    }
+
+    /// Replaces the tiles on the board. Rows are listed top first and every
+    /// character is a colour index in base 36, e.g. "01a", or `#` to skip
+    /// over a hole.
+    pub fn set_layout(&mut self, rows: &[&str]) {
+        let mut game_board = self.app.world.resource_mut::<GameBoard>();
+        let height = game_board.dimensions.y;
+        for (row, line) in rows.iter().enumerate() {
+            let y = height - 1 - row as u32;
+            for (x, character) in line.chars().enumerate() {
+                if character == '#' {
+                    continue;
+                }
+                let color = character.to_digit(36).unwrap() as usize;
+                let index = game_board.idx((x as u32, y));
+                game_board.forward[index] = Some(TileDesc {
+                    color: num::FromPrimitive::from_usize(color).unwrap(),
+                    mark: TileMarking::Blank,
+                    special: TileSpecial::None,
+                });
+            }
+        }
+        let tiles: Vec<(Entity, TileDesc)> = game_board
+            .backward
+            .iter()
+            .map(|(index, entity)| (*entity, game_board.forward[*index].unwrap()))
+            .collect();
+        for (entity, tile) in tiles {
+            self.app.world.entity_mut(entity).insert(tile);
+        }
+    }
+
+    pub fn click(&mut self, grid_pos: UVec2) {
+        let position = self.board().find_world_from_grid(grid_pos);
+        self.app.world.send_event(LeftClickEvent { position });
+        self.app.update();
+    }
+
+    fn send_key(&mut self, key_code: KeyCode, state: ButtonState) {
+        self.app.world.send_event(KeyboardInput {
+            key_code,
+            logical_key: Key::Unidentified(bevy::input::keyboard::NativeKey::Unidentified),
+            state,
+            window: Entity::PLACEHOLDER,
+        });
+        self.app.update();
+    }
+
+    pub fn key_down(&mut self, key_code: KeyCode) {
+        self.send_key(key_code, ButtonState::Pressed);
+    }
+
+    pub fn key_up(&mut self, key_code: KeyCode) {
+        self.send_key(key_code, ButtonState::Released);
+    }
+
+    pub fn tap(&mut self, key_code: KeyCode) {
+        self.key_down(key_code);
+        self.key_up(key_code);
+    }
+
+    /// Goes to the given state and lets it start up.
+    pub fn go_to(&mut self, state: GameState) {
+        self.app
+            .world
+            .resource_mut::<NextState<GameState>>()
+            .set(state);
+        self.app.update();
+    }
+
+    /// Presses a button on the screen being shown and waits for whatever it
+    /// starts to settle.
+    pub fn press(&mut self, button: MenuButton) {
+        let entity = self
+            .app
+            .world
+            .query::<(Entity, &MenuButton)>()
+            .iter(&self.app.world)
+            .find(|(_, shown)| **shown == button)
+            .map(|(entity, _)| entity)
+            .unwrap_or_else(|| panic!("No {:?} button on screen", button));
+        self.app
+            .world
+            .entity_mut(entity)
+            .insert(Interaction::Pressed);
+        self.settle();
+    }
+
+    /// Clicks both tiles and waits for the swap and any cascade to finish.
+    pub fn swap(&mut self, first: UVec2, second: UVec2) {
+        self.click(first);
+        self.click(second);
+        self.settle();
+    }
+
+    fn is_settled(&mut self) -> bool {
+        let moving = self
+            .app
+            .world
+            .query_filtered::<(), With<TileMoving>>()
+            .iter(&self.app.world)
+            .count();
+        let world = &self.app.world;
+        moving == 0
+            && *world.resource::<State<ResolveState>>().get() == ResolveState::Idle
+            && !world.contains_resource::<PendingSwap>()
+            && world
+                .get_resource::<GameBoard>()
+                .is_none_or(|game_board| game_board.find_matches().is_empty())
+    }
+
+    /// Advances time until nothing has moved for a few frames.
+    pub fn settle(&mut self) {
+        let mut settled_frames = 0;
+        for _ in 0..MAX_SETTLE_FRAMES {
+            self.app.update();
+            if self.is_settled() {
+                settled_frames += 1;
+                if settled_frames >= SETTLED_FRAMES {
+                    return;
+                }
+            } else {
+                settled_frames = 0;
+            }
+        }
+        panic!("Board did not settle within {} frames", MAX_SETTLE_FRAMES);
+    }
+}
+
+impl Drop for TestGame {
+    fn drop(&mut self) {
+        let save_dir = &self.app.world.resource::<SaveDir>().0;
+        let _ = std::fs::remove_dir_all(save_dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    // Five by five with a single move: swapping (2, 0) and (2, 1) lines up
+    // three 0s along the bottom, and once they clear the 1s left behind fall
+    // into a second match.
+    const LAYOUT: [&str; 5] = ["cdefg", "9a5b6", "56789", "3104h", "00112"];
+
+    fn small_config() -> GameConfig {
+        GameConfig {
+            board_width: 5,
+            board_height: 5,
+            colors: 18,
+            seed: Some(1234),
+            ..default()
+        }
+    }
+
+    fn assert_board_consistent(game: &mut TestGame) {
+        let cells = game.board().forward.len() - game.board().holes.len();
+        assert!(!game.board().has_empty_cells());
+        assert!(game
+            .board()
+            .holes
+            .iter()
+            .all(|index| game.board().forward[*index].is_none()));
+        assert!(game.board().find_matches().is_empty());
+        assert_eq!(game.board().backward.len(), cells);
+
+        let tiles: Vec<(Entity, UVec2)> = game
+            .app
+            .world
+            .query_filtered::<(Entity, &TilePosition), With<Tile>>()
+            .iter(&game.app.world)
+            .map(|(entity, position)| (entity, position.0))
+            .collect();
+        assert_eq!(tiles.len(), cells);
+        for (entity, grid_pos) in tiles {
+            let index = game.board().idx(grid_pos);
+            assert_eq!(game.board().backward[&index], entity);
+        }
+    }
+
+    #[test]
+    fn board_fills_without_matches() {
+        let mut game = TestGame::new(GameConfig::default());
+        assert_eq!(game.board().dimensions, UVec2::new(8, 8));
+        assert_board_consistent(&mut game);
+        assert!(!game.board().find_valid_moves().is_empty());
+    }
+
+    #[test]
+    fn swap_without_match_is_reverted() {
+        let mut game = TestGame::new(small_config());
+        game.set_layout(&LAYOUT);
+        let before = game.board().forward.clone();
+
+        game.swap(UVec2::new(3, 1), UVec2::new(4, 1));
+
+        assert_eq!(game.board().forward, before);
+        assert_eq!(game.score().value, 0);
+        assert_board_consistent(&mut game);
+    }
+
+    #[test]
+    fn matching_swap_cascades_and_refills() {
+        let mut game = TestGame::new(small_config());
+        game.set_layout(&LAYOUT);
+
+        game.swap(UVec2::new(2, 0), UVec2::new(2, 1));
+
+        assert!(game.score().value > 0);
+        assert!(game.score().chain >= 2);
+        assert_board_consistent(&mut game);
+    }
+
+    #[test]
+    fn same_seed_gives_same_board() {
+        let mut first = TestGame::new(small_config());
+        let mut second = TestGame::new(small_config());
+        assert_eq!(first.board().forward, second.board().forward);
+
+        first.set_layout(&LAYOUT);
+        second.set_layout(&LAYOUT);
+        first.swap(UVec2::new(2, 0), UVec2::new(2, 1));
+        second.swap(UVec2::new(2, 0), UVec2::new(2, 1));
+        assert_eq!(first.board().forward, second.board().forward);
+    }
+
+    #[test]
+    fn keyboard_cursor_swaps_tiles() {
+        let mut game = TestGame::new(small_config());
+        game.set_layout(&LAYOUT);
+
+        // The cursor starts in the middle, the first press only shows it.
+        game.tap(KeyCode::ArrowDown);
+        game.tap(KeyCode::ArrowDown);
+        game.tap(KeyCode::KeyS);
+        assert_eq!(
+            game.app.world.resource::<BoardCursor>().position,
+            UVec2::new(2, 0)
+        );
+
+        game.key_down(KeyCode::Space);
+        game.tap(KeyCode::ArrowUp);
+        game.key_up(KeyCode::Space);
+        game.settle();
+
+        assert!(game.score().value > 0);
+        assert_board_consistent(&mut game);
+    }
+
+    #[test]
+    fn dragging_past_threshold_swaps() {
+        let mut game = TestGame::new(small_config());
+        game.set_layout(&LAYOUT);
+        let start = game.board().find_world_from_grid(UVec2::new(2, 0));
+        let mut drag = PointerDrag {
+            start,
+            current: start + Vec2::new(2.0, 10.0),
+            swapped: false,
+            tile: None,
+        };
+
+        // A short drag only nudges the tile.
+        game.app.world.insert_resource(drag);
+        game.app.update();
+        assert_eq!(game.score().value, 0);
+
+        drag.current = start + Vec2::new(2.0, TILE_HEIGHT * 0.75);
+        game.app.world.insert_resource(drag);
+        game.app.update();
+        game.app.world.remove_resource::<PointerDrag>();
+        game.settle();
+
+        assert!(game.score().value > 0);
+        assert_board_consistent(&mut game);
+    }
+
+    fn one_move_level(target: u64) -> Levels {
+        Levels(vec![Level {
+            board_width: 5,
+            board_height: 5,
+            colors: 18,
+            limit: LevelLimit::Moves(1),
+            goals: vec![LevelGoal::Score(target)],
+            stars: [target, target * 2, target * 3],
+            ..default()
+        }])
+    }
+
+    fn level_result(game: &TestGame) -> Option<LevelResult> {
+        game.app.world.get_resource::<LevelResult>().copied()
+    }
+
+    fn hinted_tiles(game: &mut TestGame) -> Vec<UVec2> {
+        let mut hinted: Vec<UVec2> = game
+            .app
+            .world
+            .query_filtered::<&TilePosition, With<TileHint>>()
+            .iter(&game.app.world)
+            .map(|position| position.0)
+            .collect();
+        hinted.sort_by_key(|grid_pos| (grid_pos.x, grid_pos.y));
+        hinted
+    }
+
+    #[test]
+    fn idling_shows_a_free_hint() {
+        let mut game = TestGame::new(small_config());
+        game.set_layout(&LAYOUT);
+        for _ in 0..(HINT_DELAY / FRAME_TIME) as u32 + 2 {
+            game.app.update();
+        }
+        let hinted = hinted_tiles(&mut game);
+        assert!(game
+            .board()
+            .find_valid_moves()
+            .iter()
+            .any(|(first, second)| hinted == [*first, *second] || hinted == [*second, *first]));
+        assert_eq!(game.score().value, 0);
+    }
+
+    #[test]
+    fn hint_key_only_charges_when_there_is_a_move() {
+        let mut game = TestGame::new(small_config());
+        game.app.world.resource_mut::<Score>().value = 100;
+
+        // Every colour shows up at most twice, so nothing can match.
+        game.set_layout(&["01234", "56789", "abcde", "fgh01", "23456"]);
+        game.tap(KeyCode::KeyH);
+        assert!(hinted_tiles(&mut game).is_empty());
+        assert_eq!(game.score().value, 100);
+
+        game.set_layout(&LAYOUT);
+        game.tap(KeyCode::KeyP);
+        game.tap(KeyCode::KeyH);
+        assert!(hinted_tiles(&mut game).is_empty());
+        assert_eq!(game.score().value, 100);
+
+        game.tap(KeyCode::KeyP);
+        game.tap(KeyCode::KeyH);
+        assert_eq!(hinted_tiles(&mut game).len(), 2);
+        assert_eq!(game.score().value, 100 - HINT_COST);
+
+        // Asking again while the hint is up is free.
+        game.tap(KeyCode::KeyH);
+        assert_eq!(game.score().value, 100 - HINT_COST);
+    }
+
+    #[test]
+    fn level_files_parse() {
+        let mut count = 0;
+        for entry in std::fs::read_dir("assets/levels").unwrap() {
+            let path = entry.unwrap().path();
+            let level = Level::from_bytes(&std::fs::read(&path).unwrap());
+            assert!(level.is_ok(), "{}: {:?}", path.display(), level);
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn level_layout_places_fixed_tiles() {
+        let level = Level {
+            colors: 18,
+            limit: LevelLimit::Unlimited,
+            goals: vec![LevelGoal::Score(1_000_000)],
+            layout: LAYOUT.map(String::from).to_vec(),
+            ..default()
+        };
+        let mut game = TestGame::with(GameConfig::default(), |app| {
+            app.insert_resource(Levels(vec![level]));
+        });
+        let placed = game.board().forward.clone();
+
+        game.set_layout(&LAYOUT);
+        assert_eq!(game.board().dimensions, UVec2::new(5, 5));
+        assert_eq!(placed, game.board().forward);
+    }
+
+    #[test]
+    fn meeting_the_goal_wins_the_level() {
+        let mut game = TestGame::with(small_config(), |app| {
+            app.insert_resource(one_move_level(10));
+        });
+        game.set_layout(&LAYOUT);
+        assert_eq!(
+            game.app.world.resource::<LevelProgress>().moves_left,
+            Some(1)
+        );
+
+        game.swap(UVec2::new(2, 0), UVec2::new(2, 1));
+
+        let result = level_result(&game).unwrap();
+        assert!(result.won);
+        assert!(result.stars >= 1);
+        assert_eq!(
+            *game.app.world.resource::<State<GameState>>().get(),
+            GameState::GameOver
+        );
+    }
+
+    #[test]
+    fn running_out_of_moves_loses_the_level() {
+        let mut game = TestGame::with(small_config(), |app| {
+            app.insert_resource(one_move_level(1_000_000));
+        });
+        game.set_layout(&LAYOUT);
+
+        // A swap that gets swapped back doesn't use up the move.
+        game.swap(UVec2::new(3, 1), UVec2::new(4, 1));
+        assert_eq!(level_result(&game), None);
+
+        game.swap(UVec2::new(2, 0), UVec2::new(2, 1));
+        assert_eq!(
+            level_result(&game),
+            Some(LevelResult {
+                won: false,
+                stars: 0
+            })
+        );
+    }
+
+    #[test]
+    fn modes_start_from_the_loaded_config() {
+        let level = Level {
+            board_width: 7,
+            board_height: 6,
+            colors: 4,
+            ..default()
+        };
+        let mut game = TestGame::with(small_config(), |app| {
+            app.insert_resource(Levels(vec![level]));
+        });
+        assert_eq!(game.board().dimensions, UVec2::new(7, 6));
+
+        game.go_to(GameState::MainMenu);
+        game.press(MenuButton::Arcade);
+        let config = game.app.world.resource::<GameConfig>();
+        assert_eq!(config.mode, GameMode::Arcade);
+        assert_eq!(config.colors, small_config().colors);
+        assert_eq!(game.board().dimensions, UVec2::new(5, 5));
+    }
+
+    #[derive(Resource, Default)]
+    struct Phases(Vec<ResolveState>);
+
+    fn collect_phases(mut phases: ResMut<Phases>, mut events: EventReader<ResolvePhaseEvent>) {
+        phases
+            .0
+            .extend(events.read().map(|event| event.phase.clone()));
+    }
+
+    #[test]
+    fn swap_walks_through_resolve_phases() {
+        let mut game = TestGame::with(small_config(), |app| {
+            app.init_resource::<Phases>()
+                .add_systems(Last, collect_phases);
+        });
+        game.set_layout(&LAYOUT);
+        game.app.world.resource_mut::<Phases>().0.clear();
+
+        game.swap(UVec2::new(2, 0), UVec2::new(2, 1));
+
+        let phases = &game.app.world.resource::<Phases>().0;
+        use ResolveState::*;
+        assert_eq!(
+            phases[..6],
+            [Swapping, Matching, Clearing, Falling, Refilling, Matching]
+        );
+        assert_eq!(phases[phases.len() - 2..], [Matching, Idle]);
+    }
+
+    #[test]
+    fn resumed_game_continues_where_it_left_off() {
+        let mut saved = TestGame::new(small_config());
+        saved.set_layout(&LAYOUT);
+        saved.swap(UVec2::new(2, 0), UVec2::new(2, 1));
+        let world = &saved.app.world;
+        let save_game = SaveGame::capture(
+            saved.board(),
+            saved.score(),
+            world.resource::<GameRng>(),
+            world.resource::<GameConfig>(),
+            None,
+            None,
+        );
+
+        let mut resumed = TestGame::with(save_game.config.clone(), |app| {
+            app.insert_resource(ResumeGame(save_game));
+        });
+        assert_eq!(resumed.board().forward, saved.board().forward);
+        assert_eq!(resumed.score().value, saved.score().value);
+        assert_board_consistent(&mut resumed);
+
+        let next_move = saved.board().find_valid_moves()[0];
+        saved.swap(next_move.0, next_move.1);
+        resumed.swap(next_move.0, next_move.1);
+        assert_eq!(resumed.board().forward, saved.board().forward);
+    }
+
+    #[test]
+    fn saves_stay_in_the_save_dir() {
+        let mut game = TestGame::new(small_config());
+        let save_file = game.save_file(GameMode::Levels);
+        assert!(save_file.starts_with(std::env::temp_dir()));
+
+        game.app.world.send_event(bevy::app::AppExit);
+        game.app.update();
+        let save_game = SaveGame::load(&save_file).unwrap();
+        assert_eq!(
+            save_game.rng_word_pos,
+            game.app.world.resource::<GameRng>().rng.get_word_pos()
+        );
+
+        game.go_to(GameState::GameOver);
+        assert!(!save_file.exists());
+    }
+
+    #[test]
+    fn zen_keeps_its_own_save() {
+        let mut game = TestGame::new(GameConfig {
+            mode: GameMode::Zen,
+            ..small_config()
+        });
+        game.app.world.resource_mut::<Score>().value = 1234;
+        let board = game.board().forward.clone();
+        game.go_to(GameState::MainMenu);
+        let zen_save = game.save_file(GameMode::Zen);
+        assert!(zen_save.exists());
+
+        // An arcade round ending doesn't take the zen game with it.
+        game.press(MenuButton::Arcade);
+        game.go_to(GameState::GameOver);
+        assert!(zen_save.exists());
+
+        game.go_to(GameState::MainMenu);
+        game.press(MenuButton::Zen);
+        assert_eq!(game.board().forward, board);
+        assert_eq!(game.score().value, 1234);
+    }
+
+    #[test]
+    fn replay_reproduces_recorded_game() {
+        let path = std::env::temp_dir().join("match3-replay-test.ron");
+        let mut recorded = TestGame::with(small_config(), |app| {
+            app.insert_resource(ReplayRecorder::new(path.clone()));
+        });
+        for _ in 0..3 {
+            let (first, second) = recorded.board().find_valid_moves()[0];
+            recorded.swap(first, second);
+        }
+
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.swaps.len(), 3);
+        assert_eq!(replay.final_hash, Some(recorded.board().hash()));
+
+        let mut replayed = TestGame::with(replay.config.clone(), |app| {
+            app.insert_resource(ReplayPlayer::new(replay, false));
+        });
+        while !replayed.app.world.resource::<ReplayPlayer>().finished {
+            replayed.settle();
+        }
+        assert_eq!(replayed.board().forward, recorded.board().forward);
+    }
+
+    #[test]
+    fn level_holes_are_left_out_of_the_board() {
+        let level = Level {
+            colors: 18,
+            limit: LevelLimit::Unlimited,
+            goals: vec![LevelGoal::Score(1_000_000)],
+            layout: ["#...#", ".....", "..#..", ".....", "....."]
+                .map(String::from)
+                .to_vec(),
+            ..default()
+        };
+        let mut game = TestGame::with(GameConfig::default(), |app| {
+            app.insert_resource(Levels(vec![level]));
+        });
+        let holes: HashSet<usize> = [(0, 4), (4, 4), (2, 2)]
+            .into_iter()
+            .map(|grid_pos| game.board().idx(grid_pos))
+            .collect();
+        assert_eq!(game.board().holes, holes);
+        assert_board_consistent(&mut game);
+
+        let backgrounds = game
+            .app
+            .world
+            .query_filtered::<(), With<BlackBackground>>()
+            .iter(&game.app.world)
+            .count();
+        assert_eq!(backgrounds, 22);
+        let hole = game.board().find_world_from_grid(UVec2::new(2, 2));
+        assert_eq!(game.board().find_index_from_world(hole), None);
+    }
+
+    #[test]
+    fn clicking_stone_selects_nothing() {
+        let level = Level {
+            layout: [".....", ".....", "..X..", ".....", "....."]
+                .map(String::from)
+                .to_vec(),
+            ..default()
+        };
+        let mut game = TestGame::with(small_config(), |app| {
+            app.insert_resource(Levels(vec![level]));
+        });
+        game.click(UVec2::new(1, 2));
+        assert!(game.app.world.contains_resource::<SelectedTile>());
+
+        game.click(UVec2::new(2, 2));
+        game.app.update();
+        assert!(!game.app.world.contains_resource::<SelectedTile>());
+    }
+
+    #[test]
+    fn weighted_deal_leaves_out_colours_without_weight() {
+        let level = Level {
+            colors: 4,
+            color_weights: vec![0, 1, 1, 1],
+            ..default()
+        };
+        let game = TestGame::with(
+            GameConfig {
+                spawn_rule: SpawnRule::Weighted,
+                ..small_config()
+            },
+            |app| {
+                app.insert_resource(Levels(vec![level]));
+            },
+        );
+        assert!(game
+            .board()
+            .forward
+            .iter()
+            .flatten()
+            .all(|tile| tile.color != TileColor::LightYellow));
+    }
+
+    #[test]
+    fn arcade_clock_stops_while_paused_and_ends_the_round() {
+        let mut game = TestGame::new(GameConfig {
+            mode: GameMode::Arcade,
+            ..small_config()
+        });
+        let timer = game.app.world.resource::<ArcadeTimer>().clone();
+        assert!(timer.running);
+        assert!(timer.time_left < ARCADE_TIME);
+
+        game.tap(KeyCode::KeyP);
+        let paused = game.app.world.resource::<ArcadeTimer>().clone();
+        for _ in 0..30 {
+            game.app.update();
+        }
+        assert_eq!(*game.app.world.resource::<ArcadeTimer>(), paused);
+
+        game.tap(KeyCode::KeyP);
+        game.app.world.resource_mut::<ArcadeTimer>().time_left = 0.5;
+        for _ in 0..30 {
+            game.app.update();
+        }
+        assert_eq!(
+            *game.app.world.resource::<State<GameState>>().get(),
+            GameState::GameOver
+        );
+    }
+
+    #[test]
+    fn zen_brings_in_colours_as_the_score_grows() {
+        let mut game = TestGame::new(GameConfig {
+            mode: GameMode::Zen,
+            ..small_config()
+        });
+        assert_eq!(
+            game.app.world.resource::<GameConfig>().colors,
+            ZEN_START_COLORS
+        );
+        assert!(game
+            .board()
+            .forward
+            .iter()
+            .flatten()
+            .all(|tile| (tile.color as u32) < ZEN_START_COLORS));
+
+        game.app.world.resource_mut::<Score>().value = ZEN_POINTS_PER_COLOR * 2;
+        game.app.update();
+        assert_eq!(
+            game.app.world.resource::<GameConfig>().colors,
+            ZEN_START_COLORS + 2
+        );
+        assert_eq!(zen_colors(u64::MAX), TileColor::COUNT as u32);
+    }
+}
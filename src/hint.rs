@@ -0,0 +1,137 @@
+use crate::*;
+use bevy::prelude::*;
+use simple_easing::{expo_in, expo_out, reverse};
+
+pub const HINT_DELAY: f32 = 5.0;
+pub const HINT_COST: u64 = 50;
+const HINT_PULSE: f32 = 0.2;
+
+pub struct HintPlugin;
+
+impl Plugin for HintPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<IdleTimer>()
+            .add_systems(OnEnter(GameState::InGame), reset_idle_timer)
+            .add_systems(
+                Update,
+                (
+                    cancel_hint_on_click,
+                    tick_idle_timer.run_if(in_state(ResolveState::Idle)),
+                    reset_idle_timer.run_if(not(in_state(ResolveState::Idle))),
+                    request_hint
+                        .run_if(in_state(ResolveState::Idle))
+                        .run_if(in_state(PauseState::Running)),
+                    animate_hint,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct IdleTimer(pub Timer);
+
+impl Default for IdleTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(HINT_DELAY, TimerMode::Once))
+    }
+}
+
+#[derive(Component)]
+pub struct TileHint(pub Timer);
+
+impl Default for TileHint {
+    fn default() -> Self {
+        Self(Timer::from_seconds(0.8, TimerMode::Repeating))
+    }
+}
+
+pub fn reset_idle_timer(mut idle_timer: ResMut<IdleTimer>) {
+    idle_timer.0.reset();
+}
+
+/// Returns whether there was a move to show.
+fn show_hint(commands: &mut Commands, game_board: &mut GameBoard) -> bool {
+    // Purely cosmetic, so this doesn't draw from the game's seeded RNG.
+    let Some((first, second)) = game_board
+        .find_valid_moves()
+        .choose(&mut thread_rng())
+        .copied()
+    else {
+        return false;
+    };
+    for grid_pos in [first, second] {
+        if let Some(entity) = game_board.get_entity(grid_pos) {
+            commands.entity(entity).insert(TileHint::default());
+        }
+    }
+
+    info!(
+        "Hint: swap {}, {} and {}, {}",
+        first.x, first.y, second.x, second.y
+    );
+    true
+}
+
+pub fn tick_idle_timer(
+    mut commands: Commands,
+    mut game_board: ResMut<GameBoard>,
+    mut idle_timer: ResMut<IdleTimer>,
+    hint_query: Query<(), With<TileHint>>,
+    time: Res<Time>,
+) {
+    idle_timer.0.tick(time.delta());
+
+    if idle_timer.0.just_finished() && hint_query.is_empty() {
+        show_hint(&mut commands, &mut game_board);
+    }
+}
+
+pub fn request_hint(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut game_board: ResMut<GameBoard>,
+    mut score: ResMut<Score>,
+    hint_query: Query<(), With<TileHint>>,
+) {
+    // Only charged for when there is a move to show.
+    if keys.just_pressed(KeyCode::KeyH)
+        && hint_query.is_empty()
+        && show_hint(&mut commands, &mut game_board)
+    {
+        score.value = score.value.saturating_sub(HINT_COST);
+    }
+}
+
+pub fn cancel_hint_on_click(
+    mut commands: Commands,
+    mut left_click: EventReader<LeftClickEvent>,
+    mut idle_timer: ResMut<IdleTimer>,
+    mut hint_query: Query<(Entity, &mut Transform), With<TileHint>>,
+) {
+    if left_click.is_empty() {
+        return;
+    }
+    left_click.clear();
+    idle_timer.0.reset();
+
+    for (entity, mut transform) in hint_query.iter_mut() {
+        transform.scale = Vec3::new(SPRITE_SCALE, SPRITE_SCALE, 1.0);
+        commands.entity(entity).remove::<TileHint>();
+    }
+}
+
+pub fn animate_hint(mut hint_query: Query<(&mut Transform, &mut TileHint)>, time: Res<Time>) {
+    for (mut transform, mut hint_timer) in hint_query.iter_mut() {
+        hint_timer.0.tick(time.delta());
+        let duration = hint_timer.0.fraction();
+        let pulse = if duration < 0.5 {
+            expo_in(duration * 2.0)
+        } else {
+            reverse(expo_out((duration - 0.5) * 2.0))
+        };
+        let scale = SPRITE_SCALE * (1.0 + HINT_PULSE * pulse);
+        transform.scale = Vec3::new(scale, scale, 1.0);
+    }
+}
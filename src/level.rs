@@ -0,0 +1,689 @@
+use crate::*;
+use bevy::asset::io::Reader;
+use bevy::asset::{
+    AssetLoader, AsyncReadExt, LoadContext, LoadedFolder, RecursiveDependencyLoadState,
+};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use serde::{Deserialize, Serialize};
+
+const LEVEL_FOLDER: &str = "levels";
+
+/// Levels are loaded from the RON files in assets/levels through the asset
+/// server and played in file name order. Each one sets the board up and ends
+/// the game once its goals are met or its moves or time run out. Without any
+/// level files the game is endless.
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        // Levels inserted up front, e.g. by a test, win over the level files.
+        if app.world.contains_resource::<Levels>() {
+            app.insert_resource(LevelsLoaded);
+        } else {
+            app.init_resource::<Levels>()
+                .init_asset::<Level>()
+                .register_asset_loader(LevelLoader)
+                .add_systems(Startup, load_level_folder)
+                .add_systems(
+                    Update,
+                    (
+                        collect_levels,
+                        check_levels_loaded.run_if(not(resource_exists::<LevelsLoaded>)),
+                        restart_on_level_change,
+                    )
+                        .chain(),
+                );
+        }
+        app.init_resource::<CurrentLevel>()
+            .add_systems(
+                Update,
+                finish_loading
+                    .run_if(in_state(GameState::Loading))
+                    .run_if(resource_exists::<LevelsLoaded>),
+            )
+            .add_systems(
+                OnEnter(GameState::InGame),
+                (
+                    start_level.before(seed_game_rng),
+                    (spawn_jelly, spawn_level_hud)
+                        .after(fill_gameboard)
+                        .run_if(resource_exists::<LevelProgress>),
+                ),
+            )
+            .add_systems(OnExit(GameState::InGame), despawn_screen::<LevelText>)
+            .add_systems(
+                Update,
+                (
+                    count_moves,
+                    track_cleared_tiles,
+                    tick_level_timer,
+                    check_level_end.run_if(in_state(ResolveState::Idle)),
+                    update_level_hud,
+                )
+                    .chain()
+                    .after(score_matches)
+                    .run_if(resource_exists::<LevelProgress>)
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// What ends a level if the goals haven't been met first.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum LevelLimit {
+    Unlimited,
+    Moves(u32),
+    /// Seconds.
+    Time(f32),
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum LevelGoal {
+    Score(u64),
+    ClearColor(TileColor, u32),
+    ClearJelly,
+    /// Bring this many ingredients down to the bottom of the board. They drop
+    /// in one at a time from the columns with a spawner, or any column if the
+    /// layout has none.
+    CollectIngredients(u32),
+}
+
+/// What a cell of a level's layout holds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Cell {
+    Random,
+    Fixed(TileColor),
+    /// Not part of the board at all.
+    Hole,
+    /// Ice or a chain over a random tile, or stone in place of one.
+    Obstacle(Obstacle),
+    Spawner,
+}
+
+impl Cell {
+    /// `.` is a random tile, `#` a hole, `I` ice, `C` a chain, `X` stone, `S`
+    /// a spawner and a digit or lower case letter is a fixed tile of that
+    /// colour in base 36.
+    pub fn from_char(character: char) -> Option<Cell> {
+        match character {
+            '.' => Some(Cell::Random),
+            '#' => Some(Cell::Hole),
+            'I' => Some(Cell::Obstacle(Obstacle::Ice)),
+            'C' => Some(Cell::Obstacle(Obstacle::Chain)),
+            'X' => Some(Cell::Obstacle(Obstacle::Stone)),
+            'S' => Some(Cell::Spawner),
+            _ => character
+                .to_digit(36)
+                .filter(|_| !character.is_ascii_uppercase())
+                .and_then(num::FromPrimitive::from_u32)
+                .map(Cell::Fixed),
+        }
+    }
+}
+
+#[derive(Asset, TypePath, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Level {
+    pub name: String,
+    pub board_width: u32,
+    pub board_height: u32,
+    pub colors: u32,
+    pub limit: LevelLimit,
+    pub goals: Vec<LevelGoal>,
+    /// Scores needed for one, two and three stars.
+    pub stars: [u64; 3],
+    /// Cells covered in jelly, which is cleared by matching the tile on top.
+    pub jelly: Vec<(u32, u32)>,
+    /// Rows of cells, top row first, see Cell::from_char. When given it sets
+    /// the board size instead of board_width and board_height.
+    pub layout: Vec<String>,
+    /// Relative odds of each colour for new tiles, first colour first, used
+    /// by the weighted spawn rules.
+    pub color_weights: Vec<u32>,
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Self {
+            name: "Level".into(),
+            board_width: BOARD_WIDTH,
+            board_height: BOARD_HEIGHT,
+            colors: 6,
+            limit: LevelLimit::Moves(20),
+            goals: vec![LevelGoal::Score(1000)],
+            stars: [1000, 2000, 3000],
+            jelly: Vec::new(),
+            layout: Vec::new(),
+            color_weights: Vec::new(),
+        }
+    }
+}
+
+impl Level {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Level, ron::error::SpannedError> {
+        ron::de::from_bytes(bytes)
+    }
+
+    pub fn dimensions(&self) -> UVec2 {
+        if self.layout.is_empty() {
+            return UVec2::new(self.board_width, self.board_height);
+        }
+        let width = self.layout.iter().map(|row| row.chars().count()).max();
+        UVec2::new(width.unwrap_or(0) as u32, self.layout.len() as u32)
+    }
+
+    /// The layout as cells indexed like the board, bottom row first. Short
+    /// rows are padded with random tiles.
+    pub fn cells(&self) -> Option<Vec<Cell>> {
+        if self.layout.is_empty() {
+            return None;
+        }
+        let dimensions = self.dimensions();
+        let mut cells = vec![Cell::Random; (dimensions.x * dimensions.y) as usize];
+        for (row, line) in self.layout.iter().enumerate() {
+            let y = dimensions.y - 1 - row as u32;
+            for (x, character) in line.chars().enumerate() {
+                let index = (y * dimensions.x) as usize + x;
+                cells[index] = Cell::from_char(character).unwrap_or_else(|| {
+                    warn!("Unknown cell {:?} in level {}", character, self.name);
+                    Cell::Random
+                });
+            }
+        }
+        Some(cells)
+    }
+
+    /// The columns with a spawner in them, where ingredients drop in.
+    pub fn spawner_columns(&self) -> Vec<u32> {
+        let Some(cells) = self.cells() else {
+            return Vec::new();
+        };
+        let width = self.dimensions().x as usize;
+        let mut columns: Vec<u32> = cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| **cell == Cell::Spawner)
+            .map(|(index, _)| (index % width) as u32)
+            .collect();
+        columns.sort_unstable();
+        columns.dedup();
+        columns
+    }
+
+    pub fn stars_for(&self, score: u64) -> u32 {
+        self.stars
+            .iter()
+            .filter(|threshold| score >= **threshold)
+            .count() as u32
+    }
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+#[derive(Debug)]
+pub enum LevelLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl std::fmt::Display for LevelLoaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelLoaderError::Io(error) => write!(f, "Could not read level: {}", error),
+            LevelLoaderError::Ron(error) => write!(f, "Could not parse level: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for LevelLoaderError {}
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Level, LevelLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader
+                .read_to_end(&mut bytes)
+                .await
+                .map_err(LevelLoaderError::Io)?;
+            Level::from_bytes(&bytes).map_err(LevelLoaderError::Ron)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// Every level, in the order they are played.
+#[derive(Resource, Default, Debug)]
+pub struct Levels(pub Vec<Level>);
+
+#[derive(Resource)]
+pub struct LevelFolder(pub Handle<LoadedFolder>);
+
+/// Present once Levels holds every level file, or the files that could be
+/// loaded if some couldn't. Nothing starts a game before then.
+#[derive(Resource)]
+pub struct LevelsLoaded;
+
+/// The layout of the level being played, for create_gameboard.
+#[derive(Resource, Clone, Debug)]
+pub struct LevelLayout(pub Vec<Cell>);
+
+pub fn load_level_folder(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(LevelFolder(asset_server.load_folder(LEVEL_FOLDER)));
+}
+
+/// Rebuilds the level list whenever the folder or any level in it changes.
+pub fn collect_levels(
+    mut folder_events: EventReader<AssetEvent<LoadedFolder>>,
+    mut level_events: EventReader<AssetEvent<Level>>,
+    level_folder: Res<LevelFolder>,
+    folders: Res<Assets<LoadedFolder>>,
+    level_assets: Res<Assets<Level>>,
+    mut levels: ResMut<Levels>,
+) {
+    let changed = folder_events.read().count() + level_events.read().count() > 0;
+    let Some(folder) = folders.get(&level_folder.0) else {
+        return;
+    };
+    if !changed {
+        return;
+    }
+    let mut handles: Vec<_> = folder
+        .handles
+        .iter()
+        .filter_map(|handle| Some((handle.path()?.to_string(), handle.id().typed::<Level>())))
+        .collect();
+    handles.sort_by(|first, second| first.0.cmp(&second.0));
+    levels.0 = handles
+        .into_iter()
+        .filter_map(|(_, id)| level_assets.get(id).cloned())
+        .collect();
+
+    info!("Loaded {} levels", levels.0.len());
+}
+
+pub fn check_levels_loaded(
+    mut commands: Commands,
+    level_folder: Res<LevelFolder>,
+    asset_server: Res<AssetServer>,
+) {
+    match asset_server.get_recursive_dependency_load_state(&level_folder.0) {
+        Some(RecursiveDependencyLoadState::Loaded) => {}
+        // A missing folder or a broken file still lets the game start, with
+        // whatever levels did load.
+        Some(RecursiveDependencyLoadState::Failed) => {
+            warn!("Not every level in {} could be loaded", LEVEL_FOLDER)
+        }
+        _ => return,
+    }
+    commands.insert_resource(LevelsLoaded);
+}
+
+pub fn finish_loading(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::MainMenu);
+}
+
+/// Starts the level over when a level file is edited mid game, so changes
+/// show up straight away.
+pub fn restart_on_level_change(
+    mut commands: Commands,
+    mut level_events: EventReader<AssetEvent<Level>>,
+    game_state: Res<State<GameState>>,
+) {
+    let modified = level_events
+        .read()
+        .filter(|event| matches!(event, AssetEvent::Modified { .. }))
+        .count();
+    if modified == 0 || *game_state.get() != GameState::InGame {
+        return;
+    }
+    info!("Level changed, rebuilding the board");
+
+    commands.add(|world: &mut World| {
+        world.run_schedule(OnExit(GameState::InGame));
+        world.run_schedule(OnEnter(GameState::InGame));
+    });
+}
+
+/// Index into Levels of the level being played.
+#[derive(Resource, Default, Debug)]
+pub struct CurrentLevel(pub usize);
+
+/// How far the player has got with the current level.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LevelProgress {
+    /// Index of the level in Levels.
+    pub level: usize,
+    pub moves_left: Option<u32>,
+    pub time_left: Option<f32>,
+    /// Tiles cleared so far, indexed by colour.
+    pub cleared: Vec<u32>,
+    /// Cells that still have jelly on them.
+    pub jelly: Vec<usize>,
+    /// Ingredients still to be dropped onto the board.
+    #[serde(default)]
+    pub ingredients_left: u32,
+    /// Columns ingredients drop into, or any column when empty.
+    #[serde(default)]
+    pub ingredient_columns: Vec<u32>,
+    #[serde(default)]
+    pub ingredients_collected: u32,
+}
+
+impl LevelProgress {
+    pub fn new(index: usize, level: &Level) -> Self {
+        let (moves_left, time_left) = match level.limit {
+            LevelLimit::Unlimited => (None, None),
+            LevelLimit::Moves(moves) => (Some(moves), None),
+            LevelLimit::Time(seconds) => (None, Some(seconds)),
+        };
+        let dimensions = level.dimensions();
+        let jelly = level
+            .jelly
+            .iter()
+            .filter(|(x, y)| *x < dimensions.x && *y < dimensions.y)
+            .map(|(x, y)| (y * dimensions.x + x) as usize)
+            .collect();
+        let ingredients_left = level
+            .goals
+            .iter()
+            .map(|goal| match goal {
+                LevelGoal::CollectIngredients(count) => *count,
+                _ => 0,
+            })
+            .sum();
+        Self {
+            level: index,
+            moves_left,
+            time_left,
+            cleared: vec![0; TileColor::COUNT],
+            jelly,
+            ingredients_left,
+            ingredient_columns: level.spawner_columns(),
+            ingredients_collected: 0,
+        }
+    }
+
+    /// Puts an ingredient in the top refill cell of the first ingredient
+    /// column with any, if there are any left to drop and none on the board
+    /// already. Starting at the top, it has to be brought down. Called before
+    /// the rest of the refill.
+    pub fn drop_ingredient(&mut self, board: &mut Board) -> Option<TileSpawn> {
+        if self.ingredients_left == 0 || board.count_ingredients() > 0 {
+            return None;
+        }
+        let columns = &self.ingredient_columns;
+        let index = board
+            .refill_cells()
+            .into_iter()
+            .filter(|index| {
+                columns.is_empty() || columns.contains(&board.find_grid_from_index(*index).x)
+            })
+            .max_by_key(|index| {
+                let grid_pos = board.find_grid_from_index(*index);
+                (std::cmp::Reverse(grid_pos.x), grid_pos.y)
+            })?;
+        let tile = TileDesc::ingredient();
+        board.forward[index] = Some(tile);
+        self.ingredients_left -= 1;
+
+        info!("Dropped an ingredient in at {}", index);
+        Some(TileSpawn { index, tile })
+    }
+
+    pub fn goal_met(&self, goal: &LevelGoal, score: u64) -> bool {
+        match *goal {
+            LevelGoal::Score(target) => score >= target,
+            LevelGoal::ClearColor(color, count) => self.cleared[color as usize] >= count,
+            LevelGoal::ClearJelly => self.jelly.is_empty(),
+            LevelGoal::CollectIngredients(count) => self.ingredients_collected >= count,
+        }
+    }
+
+    pub fn goal_text(&self, goal: &LevelGoal, score: u64) -> String {
+        match *goal {
+            LevelGoal::Score(target) => format!("Score {}/{}", score.min(target), target),
+            LevelGoal::ClearColor(color, count) => format!(
+                "{:?} {}/{}",
+                color,
+                self.cleared[color as usize].min(count),
+                count
+            ),
+            LevelGoal::ClearJelly => format!("Jelly left {}", self.jelly.len()),
+            LevelGoal::CollectIngredients(count) => format!(
+                "Ingredients {}/{}",
+                self.ingredients_collected.min(count),
+                count
+            ),
+        }
+    }
+
+    pub fn out_of_moves(&self) -> bool {
+        self.moves_left == Some(0) || self.time_left.is_some_and(|time| time <= 0.0)
+    }
+}
+
+/// How the last level ended, shown on the game over screen.
+#[derive(Resource, Copy, Clone, Debug, PartialEq)]
+pub struct LevelResult {
+    pub won: bool,
+    pub stars: u32,
+}
+
+#[derive(Component)]
+pub struct LevelText;
+
+#[derive(Component)]
+pub struct Jelly(pub usize);
+
+/// Sets the board up for the current level, or carries on the level of a
+/// resumed game.
+pub fn start_level(
+    mut commands: Commands,
+    levels: Res<Levels>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut config: ResMut<GameConfig>,
+    resume: Option<Res<ResumeGame>>,
+) {
+    commands.remove_resource::<LevelResult>();
+    commands.remove_resource::<LevelProgress>();
+    commands.remove_resource::<LevelLayout>();
+
+    if config.mode != GameMode::Levels {
+        return;
+    }
+    if let Some(resume) = resume {
+        if let Some(progress) = resume.0.progress.clone() {
+            current_level.0 = progress.level;
+            commands.insert_resource(progress);
+        }
+        return;
+    }
+    let Some(level) = levels.0.get(current_level.0) else {
+        return;
+    };
+
+    let dimensions = level.dimensions();
+    config.board_width = dimensions.x;
+    config.board_height = dimensions.y;
+    config.colors = level.colors;
+    config.clamp();
+    commands.insert_resource(LevelProgress::new(current_level.0, level));
+    match level.cells() {
+        Some(cells) => commands.insert_resource(LevelLayout(cells)),
+        None => commands.remove_resource::<LevelLayout>(),
+    }
+
+    info!("Starting level {}: {}", current_level.0 + 1, level.name);
+}
+
+pub fn spawn_jelly(
+    mut commands: Commands,
+    game_board: Res<GameBoard>,
+    progress: Res<LevelProgress>,
+) {
+    commands.entity(game_board.entity).with_children(|parent| {
+        for index in progress.jelly.iter() {
+            let local = game_board.find_local_from_grid(game_board.find_grid_from_index(*index));
+            parent
+                .spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(0.9, 0.4, 0.9, 0.6),
+                        custom_size: Some(Vec2::new(TILE_WIDTH - 4.0, TILE_HEIGHT - 4.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(local.extend(1.8)),
+                    ..default()
+                })
+                .insert(Jelly(*index));
+        }
+    });
+}
+
+/// A move is a swap that was kept, swaps that get swapped back are free.
+pub fn count_moves(
+    mut phase_events: EventReader<ResolvePhaseEvent>,
+    mut progress: ResMut<LevelProgress>,
+    mut last_phase: Local<Option<ResolveState>>,
+) {
+    for event in phase_events.read() {
+        if *last_phase == Some(ResolveState::Swapping) && event.phase == ResolveState::Matching {
+            if let Some(moves_left) = progress.moves_left.as_mut() {
+                *moves_left = moves_left.saturating_sub(1);
+            }
+        }
+        *last_phase = Some(event.phase.clone());
+    }
+}
+
+pub fn track_cleared_tiles(
+    mut commands: Commands,
+    mut matched: EventReader<TilesMatchedEvent>,
+    mut progress: ResMut<LevelProgress>,
+    jelly_query: Query<(Entity, &Jelly)>,
+) {
+    for event in matched.read() {
+        for tile in event.cleared.iter() {
+            progress.cleared[tile.color as usize] += 1;
+        }
+        progress
+            .jelly
+            .retain(|index| !event.removed.contains(index));
+        progress.ingredients_collected += event.collected.len() as u32;
+    }
+    for (entity, jelly) in jelly_query.iter() {
+        if !progress.jelly.contains(&jelly.0) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub fn tick_level_timer(mut progress: ResMut<LevelProgress>, time: Res<Time>) {
+    if let Some(time_left) = progress.time_left.as_mut() {
+        *time_left = (*time_left - time.delta_seconds()).max(0.0);
+    }
+}
+
+/// Only checked once the board is idle, so a cascade always gets to finish.
+pub fn check_level_end(
+    mut commands: Commands,
+    levels: Res<Levels>,
+    current_level: Res<CurrentLevel>,
+    progress: Res<LevelProgress>,
+    score: Res<Score>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(level) = levels.0.get(current_level.0) else {
+        return;
+    };
+    let won = level
+        .goals
+        .iter()
+        .all(|goal| progress.goal_met(goal, score.value));
+    if !won && !progress.out_of_moves() {
+        return;
+    }
+    let stars = if won {
+        level.stars_for(score.value).max(1)
+    } else {
+        0
+    };
+    commands.insert_resource(LevelResult { won, stars });
+    next_state.set(GameState::GameOver);
+
+    info!(
+        "Level {} {} with {} stars",
+        current_level.0 + 1,
+        if won { "won" } else { "lost" },
+        stars
+    );
+}
+
+/// The text in the top right corner that each game mode keeps its own
+/// progress in, found again through the marker.
+pub fn spawn_mode_hud(commands: &mut Commands, name: &str, marker: impl Component) {
+    commands
+        .spawn(TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(20.0),
+                right: Val::Px(20.0),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Name::new(name.to_string()))
+        .insert(marker);
+}
+
+pub fn spawn_level_hud(mut commands: Commands) {
+    spawn_mode_hud(&mut commands, "Level", LevelText);
+}
+
+pub fn update_level_hud(
+    levels: Res<Levels>,
+    current_level: Res<CurrentLevel>,
+    progress: Res<LevelProgress>,
+    score: Res<Score>,
+    mut text_query: Query<&mut Text, With<LevelText>>,
+) {
+    let Some(level) = levels.0.get(current_level.0) else {
+        return;
+    };
+    let mut lines = vec![format!("Level {}: {}", current_level.0 + 1, level.name)];
+    if let Some(moves_left) = progress.moves_left {
+        lines.push(format!("Moves: {}", moves_left));
+    }
+    if let Some(time_left) = progress.time_left {
+        lines.push(format!("Time: {}", time_left.ceil()));
+    }
+    for goal in level.goals.iter() {
+        lines.push(progress.goal_text(goal, score.value));
+    }
+    lines.push(format!("Stars: {}/3", level.stars_for(score.value)));
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = lines.join("\n");
+    }
+}
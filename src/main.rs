@@ -1,25 +1,51 @@
 // #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod arcade;
 mod board;
 mod components;
+mod config;
 mod distance;
 mod effects;
+#[cfg(test)]
+mod harness;
+mod hint;
+mod level;
+mod menu;
+mod navigation;
 mod pointer;
+mod replay;
 mod resources;
+mod rules;
+mod save;
+mod score;
+mod spawn;
 mod states;
 mod tile;
 mod tilemove;
+mod zen;
 
+pub use crate::arcade::*;
 pub use crate::board::*;
 pub use crate::components::*;
+pub use crate::config::*;
 pub use crate::distance::CDistance;
 pub use crate::distance::LDistance;
 pub use crate::effects::*;
+pub use crate::hint::*;
+pub use crate::level::*;
+pub use crate::menu::*;
+pub use crate::navigation::*;
 pub use crate::pointer::*;
+pub use crate::replay::*;
 pub use crate::resources::*;
+pub use crate::rules::*;
+pub use crate::save::*;
+pub use crate::score::*;
+pub use crate::spawn::*;
 pub use crate::states::*;
 pub use crate::tile::*;
 pub use crate::tilemove::*;
+pub use crate::zen::*;
 
 pub use bevy::log::LogPlugin;
 pub use bevy::window::CursorGrabMode;
@@ -44,6 +70,12 @@ const TILE_HEIGHT: f32 = SHEET_TILE_HEIGHT * SPRITE_SCALE;
 const HALF_TILE_WIDTH: f32 = TILE_WIDTH / 2.0;
 const HALF_TILE_HEIGHT: f32 = TILE_HEIGHT / 2.0;
 const MIN_MATCH_LENGTH: u32 = 3;
+const MATCH_RULE: MatchRule = MatchRule::Color;
+const ARCADE_TIME: f32 = 60.0;
+const ARCADE_SPEEDUP_TIME: f32 = 120.0;
+const ARCADE_MAX_SPEEDUP: f32 = 3.0;
+const ZEN_START_COLORS: u32 = 4;
+const ZEN_POINTS_PER_COLOR: u64 = 2500;
 
 fn main() {
     App::new()
@@ -58,15 +90,38 @@ fn main() {
                 }),
         )
         .add_plugins(WorldInspectorPlugin::new())
-        .add_plugins(PointerPlugin)
-        .add_plugins(EffectsPlugin)
-        .add_plugins(TileMovePlugin)
-        .add_plugins(GameBoardPlugin)
+        .add_plugins(GamePlugin)
         .add_systems(Startup, setup_system)
         .add_systems(PostStartup, draw_background)
         .run();
 }
 
+/// All of the game's own plugins and states, without any windowing or
+/// rendering, so the game can also be run headless.
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        // The replay plugin goes first so a replay's config wins over config.ron.
+        app.add_plugins(ReplayPlugin)
+            .add_plugins(ConfigPlugin)
+            .init_state::<GameState>()
+            .add_plugins(MenuPlugin)
+            .add_plugins(PointerPlugin)
+            .add_plugins(NavigationPlugin)
+            .add_plugins(EffectsPlugin)
+            .add_plugins(TileMovePlugin)
+            .add_plugins(GameBoardPlugin)
+            .add_plugins(ScorePlugin)
+            .add_plugins(HintPlugin)
+            .add_plugins(LevelPlugin)
+            .add_plugins(SpawnPlugin)
+            .add_plugins(ArcadePlugin)
+            .add_plugins(ZenPlugin)
+            .add_plugins(SavePlugin);
+    }
+}
+
 fn setup_system(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
@@ -0,0 +1,317 @@
+use crate::*;
+use bevy::app::AppExit;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+const BUTTON_NORMAL: Color = Color::rgb(0.15, 0.15, 0.35);
+const BUTTON_HOVERED: Color = Color::rgb(0.25, 0.25, 0.55);
+const BUTTON_PRESSED: Color = Color::rgb(0.35, 0.35, 0.75);
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::MainMenu), spawn_main_menu)
+            .add_systems(
+                OnExit(GameState::MainMenu),
+                despawn_screen::<MainMenuScreen>,
+            )
+            .add_systems(OnEnter(GameState::GameOver), spawn_game_over)
+            .add_systems(
+                OnExit(GameState::GameOver),
+                despawn_screen::<GameOverScreen>,
+            )
+            .add_systems(Update, menu_buttons)
+            .add_systems(
+                Update,
+                (end_game_on_escape, toggle_pause).run_if(in_state(GameState::InGame)),
+            )
+            .init_state::<PauseState>()
+            .add_systems(
+                OnEnter(PauseState::Paused),
+                (spawn_pause_screen, pause_time),
+            )
+            .add_systems(
+                OnExit(PauseState::Paused),
+                (despawn_screen::<PauseScreen>, resume_time),
+            )
+            .add_systems(OnExit(GameState::InGame), unpause);
+    }
+}
+
+#[derive(Component)]
+pub struct MainMenuScreen;
+
+#[derive(Component)]
+pub struct GameOverScreen;
+
+#[derive(Component)]
+pub struct PauseScreen;
+
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MenuButton {
+    Play,
+    Arcade,
+    Zen,
+    Continue,
+    Quit,
+    Restart,
+    NextLevel,
+    MainMenu,
+}
+
+impl MenuButton {
+    fn label(&self) -> &'static str {
+        match self {
+            MenuButton::Play => "Play",
+            MenuButton::Arcade => "Arcade",
+            MenuButton::Zen => "Zen",
+            MenuButton::Continue => "Continue",
+            MenuButton::Quit => "Quit",
+            MenuButton::Restart => "Restart",
+            MenuButton::NextLevel => "Next Level",
+            MenuButton::MainMenu => "Main Menu",
+        }
+    }
+}
+
+fn screen_root() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(20.0),
+            ..default()
+        },
+        background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+        z_index: ZIndex::Global(10),
+        ..default()
+    }
+}
+
+fn spawn_title(parent: &mut ChildBuilder, title: &str, font_size: f32) {
+    parent.spawn(TextBundle::from_section(
+        title,
+        TextStyle {
+            font_size,
+            color: Color::WHITE,
+            ..default()
+        },
+    ));
+}
+
+fn spawn_button(parent: &mut ChildBuilder, button: MenuButton) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(250.0),
+                height: Val::Px(65.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BUTTON_NORMAL.into(),
+            ..default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            spawn_title(parent, button.label(), 36.0);
+        });
+}
+
+pub fn spawn_main_menu(mut commands: Commands, save_dir: Res<SaveDir>) {
+    commands
+        .spawn(screen_root())
+        .insert(Name::new("Main Menu"))
+        .insert(MainMenuScreen)
+        .with_children(|parent| {
+            spawn_title(parent, "Match 3", 80.0);
+            if save_dir
+                .save_file(GameMode::Levels)
+                .is_some_and(|path| path.exists())
+            {
+                spawn_button(parent, MenuButton::Continue);
+            }
+            spawn_button(parent, MenuButton::Play);
+            spawn_button(parent, MenuButton::Arcade);
+            spawn_button(parent, MenuButton::Zen);
+            spawn_button(parent, MenuButton::Quit);
+        });
+}
+
+pub fn spawn_game_over(
+    mut commands: Commands,
+    score: Res<Score>,
+    result: Option<Res<LevelResult>>,
+    levels: Res<Levels>,
+    current_level: Res<CurrentLevel>,
+    config: Res<GameConfig>,
+) {
+    let title = match result.as_deref() {
+        Some(LevelResult { won: true, .. }) => "Level Complete",
+        Some(LevelResult { won: false, .. }) => "Level Failed",
+        None if config.mode == GameMode::Arcade => "Time's Up",
+        None => "Game Over",
+    };
+    let has_next_level = current_level.0 + 1 < levels.0.len();
+
+    commands
+        .spawn(screen_root())
+        .insert(Name::new("Game Over"))
+        .insert(GameOverScreen)
+        .with_children(|parent| {
+            spawn_title(parent, title, 80.0);
+            spawn_title(parent, &format!("Final Score: {}", score.value), 40.0);
+            if let Some(result) = result.as_deref() {
+                spawn_title(parent, &format!("Stars: {}/3", result.stars), 40.0);
+                if result.won && has_next_level {
+                    spawn_button(parent, MenuButton::NextLevel);
+                }
+            }
+            spawn_button(parent, MenuButton::Restart);
+            spawn_button(parent, MenuButton::MainMenu);
+        });
+}
+
+pub fn despawn_screen<T: Component>(mut commands: Commands, screen_query: Query<Entity, With<T>>) {
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// What the menu sets up before starting a game.
+#[derive(SystemParam)]
+pub struct NewGame<'w> {
+    current_level: ResMut<'w, CurrentLevel>,
+    config: ResMut<'w, GameConfig>,
+    base_config: Res<'w, BaseConfig>,
+    save_dir: Res<'w, SaveDir>,
+}
+
+impl NewGame<'_> {
+    /// Picks the mode's saved game back up, if there is one.
+    fn resume(&self, commands: &mut Commands, mode: GameMode) {
+        let Some(path) = self.save_dir.save_file(mode).filter(|path| path.exists()) else {
+            return;
+        };
+        match SaveGame::load(&path) {
+            Ok(save_game) => {
+                commands.insert_resource(save_game.config.clone());
+                commands.insert_resource(ResumeGame(save_game));
+            }
+            Err(error) => warn!("Could not load {}: {}", path.display(), error),
+        }
+    }
+}
+
+pub fn menu_buttons(
+    mut commands: Commands,
+    mut button_query: Query<
+        (&Interaction, &MenuButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut app_exit: EventWriter<AppExit>,
+    mut new_game: NewGame,
+) {
+    for (interaction, button, mut background) in button_query.iter_mut() {
+        match interaction {
+            Interaction::Pressed => {
+                *background = BUTTON_PRESSED.into();
+                match button {
+                    MenuButton::Play => {
+                        *new_game.config = new_game.base_config.with_mode(GameMode::Levels);
+                        next_state.set(GameState::InGame);
+                    }
+                    MenuButton::Arcade => {
+                        *new_game.config = new_game.base_config.with_mode(GameMode::Arcade);
+                        next_state.set(GameState::InGame);
+                    }
+                    MenuButton::Zen => {
+                        *new_game.config = new_game.base_config.with_mode(GameMode::Zen);
+                        new_game.resume(&mut commands, GameMode::Zen);
+                        next_state.set(GameState::InGame);
+                    }
+                    MenuButton::Restart => next_state.set(GameState::InGame),
+                    MenuButton::Continue => {
+                        new_game.resume(&mut commands, GameMode::Levels);
+                        next_state.set(GameState::InGame);
+                    }
+                    MenuButton::NextLevel => {
+                        new_game.current_level.0 += 1;
+                        next_state.set(GameState::InGame);
+                    }
+                    MenuButton::MainMenu => next_state.set(GameState::MainMenu),
+                    MenuButton::Quit => {
+                        app_exit.send(AppExit);
+                    }
+                }
+                info!("Pressed {:?}", button);
+            }
+            Interaction::Hovered => *background = BUTTON_HOVERED.into(),
+            Interaction::None => *background = BUTTON_NORMAL.into(),
+        }
+    }
+}
+
+/// Zen games can't be lost, so Escape goes back to the main menu and the
+/// game is kept to carry on with later.
+pub fn end_game_on_escape(
+    keys: Res<ButtonInput<KeyCode>>,
+    config: Res<GameConfig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    if config.mode == GameMode::Zen {
+        next_state.set(GameState::MainMenu);
+        info!("Left the zen game");
+    } else {
+        next_state.set(GameState::GameOver);
+        info!("Ended the game");
+    }
+}
+
+pub fn toggle_pause(
+    keys: Res<ButtonInput<KeyCode>>,
+    pause_state: Res<State<PauseState>>,
+    mut next_state: ResMut<NextState<PauseState>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyP) {
+        return;
+    }
+    next_state.set(match pause_state.get() {
+        PauseState::Running => PauseState::Paused,
+        PauseState::Paused => PauseState::Running,
+    });
+}
+
+pub fn spawn_pause_screen(mut commands: Commands) {
+    commands
+        .spawn(screen_root())
+        .insert(Name::new("Pause"))
+        .insert(PauseScreen)
+        .with_children(|parent| {
+            spawn_title(parent, "Paused", 80.0);
+            spawn_title(parent, "Press P to carry on", 40.0);
+        });
+}
+
+pub fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+    info!("Paused");
+}
+
+pub fn resume_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+    info!("Unpaused");
+}
+
+/// Leaving the game, e.g. with Escape, never leaves it paused for next time.
+pub fn unpause(mut next_state: ResMut<NextState<PauseState>>) {
+    next_state.set(PauseState::Running);
+}
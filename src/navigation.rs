@@ -0,0 +1,223 @@
+use crate::*;
+use bevy::prelude::*;
+
+const OUTLINE_THICKNESS: f32 = 4.0;
+const OUTLINE_COLOR: Color = Color::rgb(1.0, 1.0, 1.0);
+
+/// Moves a cursor over the board with the arrow keys, WASD or a gamepad
+/// d-pad. Confirm (Space, Enter or the gamepad's south button) clicks the
+/// tile under the cursor, and holding confirm while pressing a direction
+/// swaps it with its neighbour.
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::InGame),
+            spawn_board_cursor.after(fill_gameboard),
+        )
+        .add_systems(OnExit(GameState::InGame), remove_board_cursor)
+        .add_systems(
+            Update,
+            (
+                hide_cursor_on_mouse,
+                navigate_board
+                    .before(click_processor)
+                    .run_if(in_state(ResolveState::Idle))
+                    .run_if(in_state(PauseState::Running)),
+                update_cursor_outline,
+            )
+                .chain()
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+#[derive(Resource, Copy, Clone, Debug)]
+pub struct BoardCursor {
+    pub position: UVec2,
+    pub visible: bool,
+}
+
+#[derive(Component)]
+pub struct CursorOutline;
+
+pub fn spawn_board_cursor(mut commands: Commands, game_board: Res<GameBoard>) {
+    let position = game_board.dimensions / 2;
+    let local = game_board.find_local_from_grid(position);
+    let edges = [
+        (
+            Vec2::new(0.0, HALF_TILE_HEIGHT),
+            Vec2::new(TILE_WIDTH, OUTLINE_THICKNESS),
+        ),
+        (
+            Vec2::new(0.0, -HALF_TILE_HEIGHT),
+            Vec2::new(TILE_WIDTH, OUTLINE_THICKNESS),
+        ),
+        (
+            Vec2::new(HALF_TILE_WIDTH, 0.0),
+            Vec2::new(OUTLINE_THICKNESS, TILE_HEIGHT),
+        ),
+        (
+            Vec2::new(-HALF_TILE_WIDTH, 0.0),
+            Vec2::new(OUTLINE_THICKNESS, TILE_HEIGHT),
+        ),
+    ];
+
+    commands.entity(game_board.entity).with_children(|parent| {
+        parent
+            .spawn(SpatialBundle {
+                transform: Transform::from_translation(local.extend(3.5)),
+                visibility: Visibility::Hidden,
+                ..default()
+            })
+            .insert(Name::new("Cursor"))
+            .insert(CursorOutline)
+            .with_children(|outline| {
+                for (offset, size) in edges {
+                    outline.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: OUTLINE_COLOR,
+                            custom_size: Some(size),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(offset.extend(0.0)),
+                        ..default()
+                    });
+                }
+            });
+    });
+    commands.insert_resource(BoardCursor {
+        position,
+        visible: false,
+    });
+}
+
+pub fn remove_board_cursor(mut commands: Commands) {
+    commands.remove_resource::<BoardCursor>();
+}
+
+fn pressed_direction(
+    keys: &ButtonInput<KeyCode>,
+    gamepad_buttons: &ButtonInput<GamepadButton>,
+    gamepads: &Gamepads,
+) -> Option<IVec2> {
+    let directions = [
+        (
+            KeyCode::ArrowUp,
+            KeyCode::KeyW,
+            GamepadButtonType::DPadUp,
+            IVec2::Y,
+        ),
+        (
+            KeyCode::ArrowDown,
+            KeyCode::KeyS,
+            GamepadButtonType::DPadDown,
+            IVec2::NEG_Y,
+        ),
+        (
+            KeyCode::ArrowLeft,
+            KeyCode::KeyA,
+            GamepadButtonType::DPadLeft,
+            IVec2::NEG_X,
+        ),
+        (
+            KeyCode::ArrowRight,
+            KeyCode::KeyD,
+            GamepadButtonType::DPadRight,
+            IVec2::X,
+        ),
+    ];
+    directions
+        .into_iter()
+        .find(|(arrow, letter, dpad, _)| {
+            keys.any_just_pressed([*arrow, *letter])
+                || gamepads
+                    .iter()
+                    .any(|gamepad| gamepad_buttons.just_pressed(GamepadButton::new(gamepad, *dpad)))
+        })
+        .map(|(_, _, _, direction)| direction)
+}
+
+pub fn hide_cursor_on_mouse(mouse: Res<ButtonInput<MouseButton>>, mut cursor: ResMut<BoardCursor>) {
+    if mouse.get_just_pressed().next().is_some() && cursor.visible {
+        cursor.visible = false;
+    }
+}
+
+/// Only runs while the board is idle, like mouse input.
+pub fn navigate_board(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut cursor: ResMut<BoardCursor>,
+    game_board: Res<GameBoard>,
+    selected_tile: Option<Res<SelectedTile>>,
+    mut left_click: EventWriter<LeftClickEvent>,
+) {
+    let confirm_buttons = [KeyCode::Space, KeyCode::Enter];
+    let gamepad_confirm = |check: &dyn Fn(GamepadButton) -> bool| {
+        gamepads
+            .iter()
+            .any(|gamepad| check(GamepadButton::new(gamepad, GamepadButtonType::South)))
+    };
+    let confirm_pressed = keys.any_just_pressed(confirm_buttons)
+        || gamepad_confirm(&|button| gamepad_buttons.just_pressed(button));
+    let confirm_held = keys.any_pressed(confirm_buttons)
+        || gamepad_confirm(&|button| gamepad_buttons.pressed(button));
+    let direction = pressed_direction(&keys, &gamepad_buttons, &gamepads);
+    if !confirm_pressed && direction.is_none() {
+        return;
+    }
+    // The first press only brings the cursor up where it was left.
+    if !cursor.visible {
+        cursor.visible = true;
+        return;
+    }
+
+    let mut click = |grid_pos: UVec2| {
+        left_click.send(LeftClickEvent {
+            position: game_board.find_world_from_grid(grid_pos),
+        });
+    };
+
+    if confirm_pressed {
+        click(cursor.position);
+    }
+    let Some(direction) = direction else {
+        return;
+    };
+    let target = cursor.position.as_ivec2() + direction;
+    if target.cmplt(IVec2::ZERO).any() || target.cmpge(game_board.dimensions.as_ivec2()).any() {
+        return;
+    }
+    let target = target.as_uvec2();
+
+    if confirm_held && !confirm_pressed {
+        let selected = selected_tile.map(|selected| selected.as_uvec2());
+        if selected != Some(cursor.position) {
+            click(cursor.position);
+        }
+        click(target);
+    }
+    cursor.position = target;
+}
+
+pub fn update_cursor_outline(
+    cursor: Res<BoardCursor>,
+    game_board: Res<GameBoard>,
+    mut outline_query: Query<(&mut Transform, &mut Visibility), With<CursorOutline>>,
+) {
+    if !cursor.is_changed() {
+        return;
+    }
+    for (mut transform, mut visibility) in outline_query.iter_mut() {
+        let local = game_board.find_local_from_grid(cursor.position);
+        transform.translation = local.extend(transform.translation.z);
+        *visibility = if cursor.visible {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
@@ -3,120 +3,263 @@ use bevy::{prelude::*, window::PrimaryWindow};
 use crate::board::*;
 use crate::distance::LDistance;
 use crate::*;
+
+/// How far the pointer has to be dragged to swap, and how much of that the
+/// tile follows.
+const DRAG_THRESHOLD: f32 = HALF_TILE_WIDTH;
+const DRAG_FOLLOW: f32 = 0.4;
+
 pub struct PointerPlugin;
 
 impl Plugin for PointerPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<LeftClickEvent>()
-            .add_systems(Update, cursor_system)
-            .add_systems(Update, click_processor.after(cursor_system));
+            .add_event::<SwapRequestEvent>()
+            .add_systems(
+                Update,
+                cursor_system
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(in_state(PauseState::Running)),
+            )
+            .add_systems(
+                Update,
+                (
+                    drag_processor
+                        .run_if(resource_exists::<PointerDrag>)
+                        .run_if(in_state(ResolveState::Idle)),
+                    release_drag,
+                    click_processor.run_if(in_state(ResolveState::Idle)),
+                )
+                    .chain()
+                    .after(cursor_system)
+                    .run_if(in_state(GameState::InGame)),
+            );
     }
 }
+/// A click on the board, from the mouse or the keyboard and gamepad cursor.
 #[derive(Event)]
 pub struct LeftClickEvent {
     pub position: Vec2,
 }
 
+/// A press on the board that hasn't been let go yet. The positions are in
+/// world space.
+#[derive(Resource, Copy, Clone, Debug)]
+pub struct PointerDrag {
+    pub start: Vec2,
+    pub current: Vec2,
+    pub swapped: bool,
+    pub tile: Option<Entity>,
+}
+
+/// Asks the board to swap two adjacent tiles. Every swap, whether it comes
+/// from the mouse or a replay, goes through this event.
+#[derive(Event, Copy, Clone, Debug)]
+pub struct SwapRequestEvent {
+    pub first: UVec2,
+    pub second: UVec2,
+}
+
+/// Clicks with the mouse or a touch, and keeps track of the pointer while it
+/// is held down so it can be dragged.
 fn cursor_system(
+    mut commands: Commands,
     btn: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
     camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
     window_query: Query<&Window, With<PrimaryWindow>>,
     mut left_click: EventWriter<LeftClickEvent>,
+    drag: Option<ResMut<PointerDrag>>,
 ) {
-    let (camera, camera_transform) = camera_query.single();
-    let window = window_query.get_single().unwrap();
-
-    if btn.just_pressed(MouseButton::Left) {
-        if let Some(world_position) = window
-            .cursor_position()
-            .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-            .map(|ray| ray.origin.truncate())
-        {
-            left_click.send(LeftClickEvent {
-                position: (world_position.x, world_position.y).into(),
-            });
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
 
-            info!(
-                "Clicked! World coords: {}/{}",
-                world_position.x, world_position.y
-            );
+    // A touch takes over from the mouse while it is down.
+    let (screen_position, just_pressed, released) = match touches.first_pressed_position() {
+        Some(position) => (
+            Some(position),
+            touches.any_just_pressed(),
+            touches.any_just_released(),
+        ),
+        None => (
+            window.cursor_position(),
+            btn.just_pressed(MouseButton::Left),
+            btn.just_released(MouseButton::Left) || touches.any_just_released(),
+        ),
+    };
+    let world_position = screen_position
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+        .map(|ray| ray.origin.truncate());
+
+    if released {
+        commands.remove_resource::<PointerDrag>();
+    }
+    let Some(world_position) = world_position else {
+        return;
+    };
+
+    if just_pressed {
+        left_click.send(LeftClickEvent {
+            position: world_position,
+        });
+        commands.insert_resource(PointerDrag {
+            start: world_position,
+            current: world_position,
+            swapped: false,
+            tile: None,
+        });
+
+        info!(
+            "Clicked! World coords: {}/{}",
+            world_position.x, world_position.y
+        );
+    } else if let Some(mut drag) = drag {
+        if !released {
+            drag.current = world_position;
         }
     }
 }
-fn click_processor(
+
+/// Drags the pressed tile a little way after the pointer, and swaps it with
+/// its neighbour once the pointer has gone far enough in one direction. The
+/// swap is made with clicks, the same as clicking both tiles.
+pub fn drag_processor(
+    mut drag: ResMut<PointerDrag>,
+    game_board: Res<GameBoard>,
+    selected_tile: Option<Res<SelectedTile>>,
+    mut left_click: EventWriter<LeftClickEvent>,
+    mut tile_query: Query<&mut Transform, (With<Tile>, Without<TileMoving>)>,
+) {
+    if drag.swapped {
+        return;
+    }
+    let Some(grid_pos) = game_board.find_grid_from_world(drag.start) else {
+        return;
+    };
+    let Some(entity) = game_board.backward.get(&game_board.idx(grid_pos)).copied() else {
+        return;
+    };
+    let Ok(mut transform) = tile_query.get_mut(entity) else {
+        return;
+    };
+    drag.tile = Some(entity);
+    let resting = game_board.find_local_from_grid(grid_pos);
+
+    let delta = drag.current - drag.start;
+    let direction = if delta.x.abs() >= delta.y.abs() {
+        IVec2::new(delta.x.signum() as i32, 0)
+    } else {
+        IVec2::new(0, delta.y.signum() as i32)
+    };
+    let distance = delta.x.abs().max(delta.y.abs());
+    let target = grid_pos.as_ivec2() + direction;
+    let on_board =
+        target.cmpge(IVec2::ZERO).all() && target.cmplt(game_board.dimensions.as_ivec2()).all();
+
+    if distance < DRAG_THRESHOLD || !on_board || direction == IVec2::ZERO {
+        let follow = direction.as_vec2() * distance.min(DRAG_THRESHOLD) * DRAG_FOLLOW;
+        transform.translation = (resting + follow).extend(transform.translation.z);
+        return;
+    }
+
+    transform.translation = resting.extend(transform.translation.z);
+    drag.swapped = true;
+    let target = target.as_uvec2();
+    if selected_tile.map(|selected| selected.as_uvec2()) != Some(grid_pos) {
+        left_click.send(LeftClickEvent {
+            position: game_board.find_world_from_grid(grid_pos),
+        });
+    }
+    left_click.send(LeftClickEvent {
+        position: game_board.find_world_from_grid(target),
+    });
+
+    info!(
+        "Dragged {}, {} towards {}, {}",
+        grid_pos.x, grid_pos.y, target.x, target.y
+    );
+}
+
+/// Puts a dragged tile back where it belongs when the pointer lets go.
+pub fn release_drag(
+    drag: Option<Res<PointerDrag>>,
+    game_board: Res<GameBoard>,
+    mut tile_query: Query<(&mut Transform, &TilePosition), Without<TileMoving>>,
+    mut dragged_tile: Local<Option<Entity>>,
+) {
+    if let Some(drag) = drag {
+        *dragged_tile = drag.tile;
+        return;
+    }
+    let Some(entity) = dragged_tile.take() else {
+        return;
+    };
+    if let Ok((mut transform, position)) = tile_query.get_mut(entity) {
+        let resting = game_board.find_local_from_grid(position.0);
+        transform.translation = resting.extend(transform.translation.z);
+    }
+}
+
+/// Turns clicks into selections and swap requests. Clicks are handled in
+/// order, so a click that selects a tile and one next to it in the same
+/// frame make a swap.
+pub fn click_processor(
     mut commands: Commands,
     mut left_click: EventReader<LeftClickEvent>,
-    mut game_board: ResMut<GameBoard>,
+    game_board: Res<GameBoard>,
     selected_tile: Option<ResMut<SelectedTile>>,
+    mut swap_request: EventWriter<SwapRequestEvent>,
 ) {
     if !left_click.is_empty() {
-        let selected_pos = selected_tile.map(|x| x.as_uvec2());
+        let mut selected_pos = selected_tile.map(|x| x.as_uvec2());
 
         for event in left_click.read() {
-            match game_board.find_grid_from_world(event.position) {
+            // Stone and holes have no tile to pick up.
+            let grid_pos = game_board
+                .find_grid_from_world(event.position)
+                .filter(|grid_pos| game_board.get_tile(*grid_pos).is_some());
+            match grid_pos {
                 Some(grid_pos) => match selected_pos {
-                    Some(selected_pos) => {
-                        let distance = grid_pos.ldistance(selected_pos);
+                    Some(current_pos) => {
+                        let distance = grid_pos.ldistance(current_pos);
                         match distance {
                             0 => {
-                                commands.remove_resource::<SelectedTile>();
+                                selected_pos = None;
 
                                 info!("Deselected Tile: {}, {}", grid_pos.x, grid_pos.y);
                             }
-                            1 => {
-                                let tile1_index = game_board.idx(grid_pos);
-                                let tile2_index = game_board.idx(selected_pos);
-
-                                let tile1 = game_board.forward.get(tile1_index).copied().unwrap();
-                                let tile2 = game_board.forward.get(tile2_index).copied().unwrap();
+                            1 if !game_board.can_swap(grid_pos, current_pos) => {
+                                selected_pos = Some(grid_pos);
 
-                                let tile1_entity = game_board.get_entity(grid_pos).unwrap();
-                                let tile2_entity = game_board.get_entity(selected_pos).unwrap();
-
-                                game_board.forward[tile2_index] = tile1;
-                                game_board.forward[tile1_index] = tile2;
-
-                                game_board.backward.insert(tile2_index, tile1_entity);
-                                game_board.backward.insert(tile1_index, tile2_entity);
-
-                                let grid_transform = game_board.find_local_from_grid(grid_pos);
-                                let selected_transform =
-                                    game_board.find_local_from_grid(selected_pos);
-
-                                commands.entity(tile1_entity).insert(TileMoving {
-                                    origin: grid_transform,
-                                    destination: selected_transform,
-
-                                    duration: Timer::from_seconds(0.5, TimerMode::Once),
-                                });
-                                commands.entity(tile2_entity).insert(TileMoving {
-                                    origin: selected_transform,
-                                    destination: grid_transform,
-                                    duration: Timer::from_seconds(0.5, TimerMode::Once),
+                                info!("Tile is locked, can't swap: {}, {}", grid_pos.x, grid_pos.y);
+                            }
+                            1 => {
+                                swap_request.send(SwapRequestEvent {
+                                    first: grid_pos,
+                                    second: current_pos,
                                 });
 
-                                commands.remove_resource::<SelectedTile>();
-
-                                info!(
-                                    "Swapsies! {}, {} and {}, {}",
-                                    grid_pos.x, grid_pos.y, selected_pos.x, selected_pos.y
-                                );
+                                selected_pos = None;
                             }
                             _ => {
-                                commands.insert_resource(SelectedTile(grid_pos));
+                                selected_pos = Some(grid_pos);
 
                                 info!("Changed Selected Tile: {}, {}", grid_pos.x, grid_pos.y);
                             }
                         }
                     }
                     None => {
-                        commands.insert_resource(SelectedTile(grid_pos));
+                        selected_pos = Some(grid_pos);
 
                         info!("Selected New Tile: {}, {}", grid_pos.x, grid_pos.y);
                     }
                 },
                 None => {
-                    commands.remove_resource::<SelectedTile>();
+                    selected_pos = None;
 
                     info!("Empty Tile Selected, Deselecting!");
                 }
@@ -127,5 +270,10 @@ fn click_processor(
                 event.position.x, event.position.y
             );
         }
+
+        match selected_pos {
+            Some(grid_pos) => commands.insert_resource(SelectedTile(grid_pos)),
+            None => commands.remove_resource::<SelectedTile>(),
+        }
     }
 }
@@ -0,0 +1,275 @@
+use crate::*;
+use bevy::app::AppExit;
+use bevy::core::FrameCount;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Records or plays back games. `--record <file>` writes every swap the
+/// player makes, `--replay <file>` feeds them back through the same swap
+/// path as the mouse and `--verify` exits with an error if the final board
+/// doesn't match the recording.
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let verify = args.iter().any(|arg| arg == "--verify");
+
+        if let Some(path) = arg_value(&args, "--replay") {
+            match Replay::load(Path::new(path)) {
+                Ok(replay) => {
+                    info!("Replaying {} swaps from {}", replay.swaps.len(), path);
+                    if let Some(level) = replay.level {
+                        app.insert_resource(CurrentLevel(level));
+                    }
+                    app.insert_resource(GameConfig {
+                        seed: Some(replay.seed),
+                        ..replay.config.clone()
+                    })
+                    .insert_resource(ReplayPlayer::new(replay, verify))
+                    .add_systems(
+                        Update,
+                        start_replay
+                            .after(finish_loading)
+                            .run_if(in_state(GameState::Loading))
+                            .run_if(resource_exists::<LevelsLoaded>),
+                    );
+                }
+                Err(error) => error!("Could not load replay {}: {}", path, error),
+            }
+        }
+        if let Some(path) = arg_value(&args, "--record") {
+            app.insert_resource(ReplayRecorder::new(PathBuf::from(path)));
+        }
+
+        app.add_systems(
+            OnEnter(GameState::InGame),
+            (
+                start_recording
+                    .after(create_gameboard)
+                    .run_if(resource_exists::<ReplayRecorder>),
+                restart_replay.run_if(resource_exists::<ReplayPlayer>),
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                record_swaps
+                    .after(swap_processor)
+                    .run_if(resource_exists::<ReplayRecorder>),
+                save_recording.run_if(resource_exists::<ReplayRecorder>),
+                play_replay
+                    .before(swap_processor)
+                    .run_if(resource_exists::<ReplayPlayer>),
+            )
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|position| args.get(position + 1))
+        .map(String::as_str)
+}
+
+/// A swap and the frame it was made on, counted from the start of the game.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct ReplaySwap {
+    pub frame: u32,
+    pub first: (u32, u32),
+    pub second: (u32, u32),
+}
+
+/// Everything needed to play a game again: the seed and config that dealt the
+/// board, every swap in order and the hash of the board once it settled.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: GameConfig,
+    pub swaps: Vec<ReplaySwap>,
+    pub final_hash: Option<u64>,
+    /// The level that was played, which sets the board up.
+    #[serde(default)]
+    pub level: Option<usize>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let contents = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        ron::from_str(&contents).map_err(|error| error.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+        std::fs::write(path, contents).map_err(|error| error.to_string())
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    pub path: PathBuf,
+    pub replay: Option<Replay>,
+    pub start_frame: u32,
+}
+
+impl ReplayRecorder {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            replay: None,
+            start_frame: 0,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    pub next: usize,
+    pub start_frame: u32,
+    pub verify: bool,
+    pub finished: bool,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay, verify: bool) -> Self {
+        Self {
+            replay,
+            next: 0,
+            start_frame: 0,
+            verify,
+            finished: false,
+        }
+    }
+}
+
+/// Goes straight into the game instead of the main menu, once the levels
+/// are in so the recorded level is set up the same way.
+fn start_replay(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::InGame);
+}
+
+pub fn start_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    rng: Res<GameRng>,
+    config: Res<GameConfig>,
+    frame: Res<FrameCount>,
+    progress: Option<Res<LevelProgress>>,
+) {
+    recorder.start_frame = frame.0;
+    recorder.replay = Some(Replay {
+        seed: rng.seed,
+        config: config.clone(),
+        swaps: Vec::new(),
+        final_hash: None,
+        level: progress.map(|progress| progress.level),
+    });
+
+    info!("Recording to {}", recorder.path.display());
+}
+
+pub fn restart_replay(mut player: ResMut<ReplayPlayer>, frame: Res<FrameCount>) {
+    player.next = 0;
+    player.start_frame = frame.0;
+    player.finished = false;
+}
+
+pub fn record_swaps(
+    mut recorder: ResMut<ReplayRecorder>,
+    pending_swap: Option<Res<PendingSwap>>,
+    frame: Res<FrameCount>,
+) {
+    let Some(pending_swap) = pending_swap.filter(|swap| swap.is_added()) else {
+        return;
+    };
+    let frame = frame.0.wrapping_sub(recorder.start_frame);
+    if let Some(replay) = recorder.replay.as_mut() {
+        replay.swaps.push(ReplaySwap {
+            frame,
+            first: pending_swap.first.into(),
+            second: pending_swap.second.into(),
+        });
+    }
+}
+
+/// Writes the recording every time the board settles, so it is complete
+/// however the game ends.
+pub fn save_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    mut settled: EventReader<BoardSettledEvent>,
+    game_board: Res<GameBoard>,
+) {
+    if settled.is_empty() {
+        return;
+    }
+    settled.clear();
+
+    let path = recorder.path.clone();
+    if let Some(replay) = recorder.replay.as_mut() {
+        replay.final_hash = Some(game_board.hash());
+        if let Err(error) = replay.save(&path) {
+            warn!("Could not save replay to {}: {}", path.display(), error);
+        }
+    }
+}
+
+pub fn play_replay(
+    mut player: ResMut<ReplayPlayer>,
+    mut settled: EventReader<BoardSettledEvent>,
+    mut swap_request: EventWriter<SwapRequestEvent>,
+    mut app_exit: EventWriter<AppExit>,
+    game_board: Res<GameBoard>,
+    resolve_state: Res<State<ResolveState>>,
+    frame: Res<FrameCount>,
+) {
+    let just_settled = !settled.is_empty();
+    settled.clear();
+    if player.finished {
+        return;
+    }
+
+    if player.next == player.replay.swaps.len() {
+        if just_settled {
+            player.finished = true;
+            finish_replay(&player, game_board.hash(), &mut app_exit);
+        }
+        return;
+    }
+
+    // Swaps are only played once the board is idle, just like the player
+    // could only make them once it was.
+    let swap = player.replay.swaps[player.next];
+    if *resolve_state.get() != ResolveState::Idle
+        || frame.0.wrapping_sub(player.start_frame) < swap.frame
+    {
+        return;
+    }
+    swap_request.send(SwapRequestEvent {
+        first: swap.first.into(),
+        second: swap.second.into(),
+    });
+    player.next += 1;
+}
+
+fn finish_replay(player: &ReplayPlayer, hash: u64, app_exit: &mut EventWriter<AppExit>) {
+    match player.replay.final_hash {
+        Some(expected) if expected == hash => info!("Replay finished, board hash {:x}", hash),
+        Some(expected) => error!(
+            "Replay diverged: board hash {:x}, recorded {:x}",
+            hash, expected
+        ),
+        None => warn!("Replay has no final board hash to verify against"),
+    }
+    if !player.verify {
+        return;
+    }
+    if player.replay.final_hash == Some(hash) {
+        app_exit.send(AppExit);
+    } else {
+        std::process::exit(1);
+    }
+}
@@ -1,4 +1,6 @@
 use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
 #[derive(Resource)]
 pub struct GameAssets {
@@ -19,3 +21,35 @@ impl SelectedTile {
         self.0
     }
 }
+
+/// A swap made by the player that is still animating. Once the tiles settle it
+/// is either kept, if it made a match, or swapped back.
+#[derive(Resource, Copy, Clone, Debug)]
+pub struct PendingSwap {
+    pub first: UVec2,
+    pub second: UVec2,
+}
+
+/// The random number generator behind every board change. The same seed and
+/// the same swaps always produce the same board history.
+#[derive(Resource, Clone, Debug)]
+pub struct GameRng {
+    pub seed: u64,
+    pub rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Picks the sequence back up where a saved game left it.
+    pub fn resume(seed: u64, word_pos: u128) -> Self {
+        let mut game_rng = Self::new(seed);
+        game_rng.rng.set_word_pos(word_pos);
+        game_rng
+    }
+}
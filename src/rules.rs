@@ -0,0 +1,1104 @@
+use crate::tile::{MatchRule, TileDesc, TileSpecial};
+use bevy::math::{IVec2, UVec2};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+const MAX_SHUFFLE_ATTEMPTS: u32 = 100;
+
+// The pure game rules. Nothing in here touches the ECS, so a Board can be
+// created, resolved and inspected without a window or a renderer.
+
+pub trait Index2D<RHS = Self> {
+    fn idx(&self, grid_pos: RHS) -> usize;
+}
+
+/// What a cell can hold besides its tile.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Obstacle {
+    #[default]
+    None,
+    /// Sits under the tile and breaks when the tile is matched.
+    Ice,
+    /// Holds the tile in place, so it can't be swapped and doesn't fall. The
+    /// tile can still be matched, which breaks the chain too.
+    Chain,
+    /// Takes the place of a tile. It can't be matched, tiles can't fall
+    /// through it and it breaks when a match happens next to it.
+    Stone,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Board {
+    pub dimensions: UVec2,
+    pub forward: Vec<Option<TileDesc>>,
+    /// Cells that aren't part of the board. They never hold a tile, but tiles
+    /// fall past them.
+    pub holes: HashSet<usize>,
+    /// The obstacle layer, one entry per cell like forward.
+    pub obstacles: Vec<Obstacle>,
+    pub min_match_length: u32,
+    pub match_rule: MatchRule,
+}
+
+/// A tile that moved from one index to another, by gravity or a reshuffle.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TileMove {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// A new tile that was dropped into an empty index during refill.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TileSpawn {
+    pub index: usize,
+    pub tile: TileDesc,
+}
+
+/// A group of runs that share tiles, e.g. a straight line or an L/T shape,
+/// along with the special tile it earns and where that tile is created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchShape {
+    pub tiles: Vec<usize>,
+    pub special: TileSpecial,
+    pub anchor: usize,
+}
+
+/// Everything cleared by a single matching step.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Resolution {
+    pub runs: Vec<Vec<usize>>,
+    pub upgraded: Vec<TileSpawn>,
+    pub removed: Vec<usize>,
+    pub cleared: Vec<TileDesc>,
+    /// Cells whose obstacle broke, sorted.
+    pub broken: Vec<usize>,
+    /// Cells of ingredients that reached the bottom and were taken off the
+    /// board, sorted.
+    pub collected: Vec<usize>,
+}
+
+impl Resolution {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.broken.is_empty() && self.collected.is_empty()
+    }
+}
+
+impl Index2D<(u32, u32)> for Board {
+    fn idx(&self, grid_pos: (u32, u32)) -> usize {
+        grid_pos.1 as usize * self.dimensions.x as usize + grid_pos.0 as usize
+    }
+}
+
+impl Index2D<UVec2> for Board {
+    fn idx(&self, grid_pos: UVec2) -> usize {
+        grid_pos.y as usize * self.dimensions.x as usize + grid_pos.x as usize
+    }
+}
+
+impl Board {
+    pub fn new(dimensions: UVec2, min_match_length: u32, match_rule: MatchRule) -> Board {
+        Self {
+            dimensions,
+            forward: vec![None; (dimensions.x * dimensions.y) as usize],
+            holes: HashSet::new(),
+            obstacles: vec![Obstacle::None; (dimensions.x * dimensions.y) as usize],
+            min_match_length,
+            match_rule,
+        }
+    }
+
+    pub fn fill(&mut self, mut new_tile: impl FnMut() -> TileDesc) {
+        for index in 0..self.forward.len() {
+            if self.holds_tiles(index) {
+                self.forward[index] = Some(new_tile());
+            }
+        }
+    }
+
+    /// Cuts a cell out of the board, taking its tile with it.
+    pub fn add_hole(&mut self, index: usize) {
+        self.forward[index] = None;
+        self.holes.insert(index);
+    }
+
+    pub fn is_hole(&self, index: usize) -> bool {
+        self.holes.contains(&index)
+    }
+
+    /// Puts an obstacle in a cell. Stone takes the place of the tile.
+    pub fn add_obstacle(&mut self, index: usize, obstacle: Obstacle) {
+        if obstacle == Obstacle::Stone {
+            self.forward[index] = None;
+        }
+        self.obstacles[index] = obstacle;
+    }
+
+    /// Whether the cell is part of the board and not taken up by stone.
+    pub fn holds_tiles(&self, index: usize) -> bool {
+        !self.is_hole(index) && self.obstacles[index] != Obstacle::Stone
+    }
+
+    /// Cells that stay put while everything else falls, and that nothing
+    /// falls through.
+    pub fn is_fixed(&self, index: usize) -> bool {
+        matches!(self.obstacles[index], Obstacle::Chain | Obstacle::Stone)
+    }
+
+    /// Whether new tiles drop into this column. A hole in the top row caps
+    /// it, so the cells below can only be filled from the side.
+    pub fn is_column_open(&self, x: u32) -> bool {
+        !self.is_hole(self.idx((x, self.dimensions.y - 1)))
+    }
+
+    /// Whether any cell of the board is waiting for a tile.
+    pub fn has_empty_cells(&self) -> bool {
+        (0..self.forward.len())
+            .any(|index| self.forward[index].is_none() && self.holds_tiles(index))
+    }
+
+    pub fn find_grid_from_index(&self, index: usize) -> UVec2 {
+        UVec2::new(
+            (index % self.dimensions.x as usize) as u32,
+            (index / self.dimensions.x as usize) as u32,
+        )
+    }
+
+    /// FNV-1a over every cell, so two boards can be compared without keeping
+    /// them around. It is stable between builds, unlike the std hasher.
+    pub fn hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for (tile, obstacle) in self.forward.iter().zip(self.obstacles.iter()) {
+            let mut bytes = match tile {
+                Some(tile) => vec![1, tile.color as u8, tile.mark as u8, tile.special as u8],
+                None => vec![0; 4],
+            };
+            // Cells without an obstacle hash the same as before there were any.
+            if *obstacle != Obstacle::None {
+                bytes.push(*obstacle as u8);
+            }
+            for byte in bytes {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    pub fn get_tile(&self, grid_pos: UVec2) -> Option<TileDesc> {
+        self.forward[self.idx(grid_pos)]
+    }
+
+    pub fn swap(&mut self, first: usize, second: usize) {
+        self.forward.swap(first, second);
+    }
+
+    fn tiles_match(&self, first: usize, second: usize) -> bool {
+        match (self.forward[first], self.forward[second]) {
+            (Some(first), Some(second)) => first.matches(&second, self.match_rule),
+            _ => false,
+        }
+    }
+
+    // Walks a single row or column and pushes every run of at least
+    // min_match_length matching tiles.
+    fn resolve_line(&self, line: &[usize], runs: &mut Vec<Vec<usize>>) {
+        let mut run_start = 0;
+        for position in 1..=line.len() {
+            if position < line.len() && self.tiles_match(line[run_start], line[position]) {
+                continue;
+            }
+            if (position - run_start) as u32 >= self.min_match_length {
+                runs.push(line[run_start..position].to_vec());
+            }
+            run_start = position;
+        }
+    }
+
+    pub fn horizontal_runs(&self) -> Vec<Vec<usize>> {
+        let mut runs = Vec::new();
+        for y in 0..self.dimensions.y {
+            let line: Vec<usize> = (0..self.dimensions.x).map(|x| self.idx((x, y))).collect();
+            self.resolve_line(&line, &mut runs);
+        }
+        runs
+    }
+
+    pub fn vertical_runs(&self) -> Vec<Vec<usize>> {
+        let mut runs = Vec::new();
+        for x in 0..self.dimensions.x {
+            let line: Vec<usize> = (0..self.dimensions.y).map(|y| self.idx((x, y))).collect();
+            self.resolve_line(&line, &mut runs);
+        }
+        runs
+    }
+
+    pub fn find_runs(&self) -> Vec<Vec<usize>> {
+        let mut runs = self.horizontal_runs();
+        runs.extend(self.vertical_runs());
+        runs
+    }
+
+    /// Groups horizontal and vertical runs that share a tile into shapes. The
+    /// special tile is placed on a focus cell (the player's swap) if the shape
+    /// covers one, otherwise on the corner of an L/T or the middle of the line.
+    pub fn find_shapes(&self, focus: &[usize]) -> Vec<MatchShape> {
+        let horizontal = self.horizontal_runs();
+        let horizontal_count = horizontal.len();
+        let runs: Vec<Vec<usize>> = horizontal.into_iter().chain(self.vertical_runs()).collect();
+
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (run_id, run) in runs.iter().enumerate() {
+            let (touching, mut rest): (Vec<Vec<usize>>, Vec<Vec<usize>>) =
+                groups.into_iter().partition(|group| {
+                    group
+                        .iter()
+                        .any(|other| runs[*other].iter().any(|index| run.contains(index)))
+                });
+            let mut merged: Vec<usize> = touching.into_iter().flatten().collect();
+            merged.push(run_id);
+            rest.push(merged);
+            groups = rest;
+        }
+
+        groups
+            .into_iter()
+            .map(|group| {
+                let mut tiles: Vec<usize> = group.iter().flat_map(|id| runs[*id].clone()).collect();
+                tiles.sort_unstable();
+                tiles.dedup();
+
+                let has_horizontal = group.iter().any(|id| *id < horizontal_count);
+                let has_vertical = group.iter().any(|id| *id >= horizontal_count);
+                let longest = *group.iter().max_by_key(|id| runs[**id].len()).unwrap();
+                let longest_len = runs[longest].len();
+
+                let special = if longest_len >= 5 {
+                    TileSpecial::ColorBomb
+                } else if has_horizontal && has_vertical {
+                    TileSpecial::Bomb
+                } else if longest_len == 4 && longest < horizontal_count {
+                    TileSpecial::RowClearer
+                } else if longest_len == 4 {
+                    TileSpecial::ColumnClearer
+                } else {
+                    TileSpecial::None
+                };
+
+                let corner = tiles
+                    .iter()
+                    .copied()
+                    .find(|index| group.iter().filter(|id| runs[**id].contains(index)).count() > 1);
+                let anchor = tiles
+                    .iter()
+                    .copied()
+                    .find(|index| focus.contains(index))
+                    .or(corner)
+                    .unwrap_or(runs[longest][longest_len / 2]);
+
+                MatchShape {
+                    tiles,
+                    special,
+                    anchor,
+                }
+            })
+            .collect()
+    }
+
+    /// The cells a special tile clears when it goes off at the given index.
+    pub fn activation_area(&self, index: usize, tile: TileDesc) -> Vec<usize> {
+        let grid_pos = self.find_grid_from_index(index);
+        match tile.special {
+            TileSpecial::None | TileSpecial::Ingredient => vec![index],
+            TileSpecial::RowClearer => (0..self.dimensions.x)
+                .map(|x| self.idx((x, grid_pos.y)))
+                .collect(),
+            TileSpecial::ColumnClearer => (0..self.dimensions.y)
+                .map(|y| self.idx((grid_pos.x, y)))
+                .collect(),
+            TileSpecial::Bomb => {
+                let mut area = Vec::new();
+                for y in grid_pos.y.saturating_sub(1)..=(grid_pos.y + 1) {
+                    for x in grid_pos.x.saturating_sub(1)..=(grid_pos.x + 1) {
+                        if x < self.dimensions.x && y < self.dimensions.y {
+                            area.push(self.idx((x, y)));
+                        }
+                    }
+                }
+                area
+            }
+            TileSpecial::ColorBomb => self.indices_matching(tile),
+        }
+    }
+
+    fn indices_matching(&self, tile: TileDesc) -> Vec<usize> {
+        (0..self.forward.len())
+            .filter(|index| {
+                matches!(self.forward[*index], Some(other) if other.matches(&tile, self.match_rule))
+            })
+            .collect()
+    }
+
+    /// The cells cleared when the player swaps a special tile. A colour bomb
+    /// clears every tile of its partner's colour, or the whole board when
+    /// swapped with another colour bomb. Other specials go off where they land.
+    /// Returns an empty set when neither tile is special.
+    pub fn swap_activation(&self, first: usize, second: usize) -> HashSet<usize> {
+        let mut triggered = HashSet::new();
+        let (Some(first_tile), Some(second_tile)) = (self.forward[first], self.forward[second])
+        else {
+            return triggered;
+        };
+
+        for (index, tile, partner) in [
+            (first, first_tile, second_tile),
+            (second, second_tile, first_tile),
+        ] {
+            match (tile.special, partner.special) {
+                (TileSpecial::None | TileSpecial::Ingredient, _) => {}
+                (TileSpecial::ColorBomb, TileSpecial::ColorBomb) => {
+                    triggered.extend(0..self.forward.len());
+                }
+                (TileSpecial::ColorBomb, _) => {
+                    triggered.insert(index);
+                    triggered.extend(self.indices_matching(partner));
+                }
+                _ => {
+                    triggered.insert(index);
+                }
+            }
+        }
+        triggered
+    }
+
+    // Adds the areas of every special tile caught in the clear, following
+    // chains of specials setting each other off. Protected cells are kept.
+    fn expand_activations(&self, to_be_deleted: &mut HashSet<usize>, protected: &[usize]) {
+        let mut queue: Vec<usize> = to_be_deleted.iter().copied().collect();
+        let mut activated: HashSet<usize> = HashSet::new();
+
+        while let Some(index) = queue.pop() {
+            let Some(tile) = self.forward[index] else {
+                continue;
+            };
+            if !tile.is_special() || protected.contains(&index) || !activated.insert(index) {
+                continue;
+            }
+            for cleared in self.activation_area(index, tile) {
+                if !protected.contains(&cleared) && to_be_deleted.insert(cleared) {
+                    queue.push(cleared);
+                }
+            }
+        }
+    }
+
+    pub fn resolve_horizontal_matches(&self, to_be_deleted: &mut HashSet<usize>) {
+        for run in self.horizontal_runs() {
+            to_be_deleted.extend(run);
+        }
+    }
+
+    pub fn resolve_vertical_matches(&self, to_be_deleted: &mut HashSet<usize>) {
+        for run in self.vertical_runs() {
+            to_be_deleted.extend(run);
+        }
+    }
+
+    pub fn find_matches(&self) -> HashSet<usize> {
+        let mut to_be_deleted: HashSet<usize> = HashSet::new();
+        self.resolve_horizontal_matches(&mut to_be_deleted);
+        self.resolve_vertical_matches(&mut to_be_deleted);
+        to_be_deleted
+    }
+
+    /// Empties the given cells and returns the removed indices, sorted, along
+    /// with the tiles that were in them. Cells without a tile are skipped.
+    pub fn remove_tiles(&mut self, to_be_deleted: &HashSet<usize>) -> (Vec<usize>, Vec<TileDesc>) {
+        let mut removed: Vec<usize> = to_be_deleted
+            .iter()
+            .copied()
+            .filter(|index| self.forward[*index].is_some())
+            .collect();
+        removed.sort_unstable();
+        let cleared = removed
+            .iter()
+            .filter_map(|index| self.forward[*index].take())
+            .collect();
+        (removed, cleared)
+    }
+
+    /// Drops every tile down into the empty cells below it, falling past any
+    /// holes but stopping on fixed cells. Cells that nothing can fall into from
+    /// above, because their column is capped or blocked, take a tile sliding in
+    /// diagonally from a neighbouring column instead. Each tile gets a single
+    /// move, from where it started to where it came to rest.
+    pub fn apply_gravity(&mut self) -> Vec<TileMove> {
+        // Where each tile that moved started, keyed by where it is now.
+        let mut started: HashMap<usize, usize> = HashMap::new();
+        let mut step = |board: &mut Board, from: usize, to: usize| {
+            board.forward.swap(from, to);
+            let origin = started.remove(&from).unwrap_or(from);
+            started.insert(to, origin);
+        };
+
+        loop {
+            for x in 0..self.dimensions.x {
+                // Fixed cells split the column into stretches that tiles fall
+                // down separately.
+                let column: Vec<usize> = (0..self.dimensions.y)
+                    .map(|y| self.idx((x, y)))
+                    .filter(|index| !self.is_hole(*index))
+                    .collect();
+                let stretches: Vec<Vec<usize>> = column
+                    .split(|index| self.is_fixed(*index))
+                    .map(<[usize]>::to_vec)
+                    .collect();
+                for cells in stretches {
+                    let mut next_free = 0;
+                    for position in 0..cells.len() {
+                        if self.forward[cells[position]].is_none() {
+                            continue;
+                        }
+                        if next_free != position {
+                            step(self, cells[position], cells[next_free]);
+                        }
+                        next_free += 1;
+                    }
+                }
+            }
+            match self.find_diagonal_slide() {
+                Some((from, to)) => step(self, from, to),
+                None => break,
+            }
+        }
+
+        let mut falls: Vec<TileMove> = started
+            .into_iter()
+            .map(|(to, from)| TileMove { from, to })
+            .collect();
+        falls.sort_unstable_by_key(|fall| fall.to);
+        falls
+    }
+
+    // The lowest empty cell that can't be reached from above, along with the
+    // tile above and to its left, or failing that its right, to slide into it.
+    fn find_diagonal_slide(&self) -> Option<(usize, usize)> {
+        for y in 0..self.dimensions.y - 1 {
+            for x in 0..self.dimensions.x {
+                let index = self.idx((x, y));
+                if self.forward[index].is_some() || !self.holds_tiles(index) {
+                    continue;
+                }
+                if self.is_fed_from_above(x, y) {
+                    continue;
+                }
+                let source = [x.checked_sub(1), Some(x + 1)]
+                    .into_iter()
+                    .flatten()
+                    .filter(|source_x| *source_x < self.dimensions.x)
+                    .map(|source_x| self.idx((source_x, y + 1)))
+                    .find(|source| self.forward[*source].is_some() && !self.is_fixed(*source));
+                if let Some(source) = source {
+                    return Some((source, index));
+                }
+            }
+        }
+        None
+    }
+
+    // Whether a tile can still drop straight into the cell: either one is
+    // waiting above it, or the column is open to refills all the way up.
+    fn is_fed_from_above(&self, x: u32, y: u32) -> bool {
+        for above in (y + 1)..self.dimensions.y {
+            let index = self.idx((x, above));
+            if self.is_hole(index) {
+                continue;
+            }
+            if self.is_fixed(index) {
+                return false;
+            }
+            if self.forward[index].is_some() {
+                return true;
+            }
+        }
+        self.is_column_open(x)
+    }
+
+    /// Ingredients with nowhere left to fall, which are ready to collect.
+    pub fn collectable_ingredients(&self) -> Vec<usize> {
+        (0..self.forward.len())
+            .filter(|index| matches!(self.forward[*index], Some(tile) if tile.is_ingredient()))
+            .filter(|index| {
+                let grid_pos = self.find_grid_from_index(*index);
+                (0..grid_pos.y).all(|below| !self.holds_tiles(self.idx((grid_pos.x, below))))
+            })
+            .collect()
+    }
+
+    pub fn count_ingredients(&self) -> usize {
+        self.forward
+            .iter()
+            .filter(|tile| matches!(tile, Some(tile) if tile.is_ingredient()))
+            .count()
+    }
+
+    /// Whether gravity would move anything, e.g. into cells that a refill
+    /// couldn't reach.
+    pub fn can_fall(&self) -> bool {
+        !self.clone().apply_gravity().is_empty()
+    }
+
+    /// The empty cells that new tiles can drop into from the top of their
+    /// column, in the order refill fills them.
+    pub fn refill_cells(&self) -> Vec<usize> {
+        let mut cells = Vec::new();
+        for x in 0..self.dimensions.x {
+            if !self.is_column_open(x) {
+                continue;
+            }
+            // New tiles can't get past the highest fixed cell.
+            let floor = (0..self.dimensions.y)
+                .rev()
+                .find(|y| self.is_fixed(self.idx((x, *y))))
+                .map_or(0, |y| y + 1);
+            for y in floor..self.dimensions.y {
+                let index = self.idx((x, y));
+                if self.forward[index].is_none() && self.holds_tiles(index) {
+                    cells.push(index);
+                }
+            }
+        }
+        cells
+    }
+
+    /// Fills every refill cell with a freshly generated tile.
+    pub fn refill(&mut self, mut new_tile: impl FnMut() -> TileDesc) -> Vec<TileSpawn> {
+        let mut spawns = Vec::new();
+        for index in self.refill_cells() {
+            let tile = new_tile();
+            self.forward[index] = Some(tile);
+            spawns.push(TileSpawn { index, tile });
+        }
+        spawns
+    }
+
+    /// Whether putting the tile in the cell would line it up with enough
+    /// matching neighbours, in its row or column, to make a run.
+    pub fn makes_match_at(&self, index: usize, tile: TileDesc) -> bool {
+        let grid_pos = self.find_grid_from_index(index).as_ivec2();
+        let dimensions = self.dimensions.as_ivec2();
+        let count_matching = |direction: IVec2| {
+            let mut count = 0;
+            let mut neighbour = grid_pos + direction;
+            while neighbour.cmpge(IVec2::ZERO).all() && neighbour.cmplt(dimensions).all() {
+                match self.forward[self.idx(neighbour.as_uvec2())] {
+                    Some(other) if other.matches(&tile, self.match_rule) => count += 1,
+                    _ => break,
+                }
+                neighbour += direction;
+            }
+            count
+        };
+        [IVec2::X, IVec2::Y]
+            .into_iter()
+            .any(|axis| 1 + count_matching(axis) + count_matching(-axis) >= self.min_match_length)
+    }
+
+    /// Finds the matches, creates special tiles and removes everything that
+    /// was cleared, leaving the empty cells for gravity and refilling. The
+    /// focus is the cells of the player's swap, which is where special tiles
+    /// are created, and triggered any cells already set off by swapping a
+    /// special tile.
+    pub fn clear_matches(&mut self, focus: &[usize], triggered: HashSet<usize>) -> Resolution {
+        let runs = self.find_runs();
+        let collected = self.collectable_ingredients();
+        if runs.is_empty() && triggered.is_empty() && collected.is_empty() {
+            return Resolution::default();
+        }
+        let mut to_be_deleted: HashSet<usize> = runs.iter().flatten().copied().collect();
+        to_be_deleted.extend(triggered);
+
+        let mut upgraded = Vec::new();
+        for shape in self.find_shapes(focus) {
+            let Some(mut tile) = self.forward[shape.anchor] else {
+                continue;
+            };
+            if shape.special == TileSpecial::None || tile.is_special() {
+                continue;
+            }
+            tile.special = shape.special;
+            upgraded.push(TileSpawn {
+                index: shape.anchor,
+                tile,
+            });
+        }
+        let protected: Vec<usize> = upgraded.iter().map(|upgrade| upgrade.index).collect();
+        self.expand_activations(&mut to_be_deleted, &protected);
+        // Ingredients can only leave the board by reaching the bottom.
+        to_be_deleted
+            .retain(|index| !matches!(self.forward[*index], Some(tile) if tile.is_ingredient()));
+        for upgrade in upgraded.iter() {
+            to_be_deleted.remove(&upgrade.index);
+            self.forward[upgrade.index] = Some(upgrade.tile);
+        }
+
+        let broken = self.break_obstacles(&to_be_deleted);
+        let (removed, cleared) = self.remove_tiles(&to_be_deleted);
+        for index in collected.iter() {
+            self.forward[*index] = None;
+        }
+
+        Resolution {
+            runs,
+            upgraded,
+            removed,
+            cleared,
+            broken,
+            collected,
+        }
+    }
+
+    // Breaks the ice and chains under cleared tiles, stone caught in a special
+    // tile's blast and stone next to any cleared tile. Returns where.
+    fn break_obstacles(&mut self, to_be_deleted: &HashSet<usize>) -> Vec<usize> {
+        let mut broken: HashSet<usize> = HashSet::new();
+        for index in to_be_deleted.iter().copied() {
+            match self.obstacles[index] {
+                Obstacle::None => {}
+                Obstacle::Ice | Obstacle::Chain if self.forward[index].is_none() => {}
+                Obstacle::Ice | Obstacle::Chain | Obstacle::Stone => {
+                    broken.insert(index);
+                }
+            }
+            if self.forward[index].is_none() {
+                continue;
+            }
+            let grid_pos = self.find_grid_from_index(index);
+            let neighbours = [
+                grid_pos.x.checked_sub(1).map(|x| UVec2::new(x, grid_pos.y)),
+                grid_pos.y.checked_sub(1).map(|y| UVec2::new(grid_pos.x, y)),
+                Some(UVec2::new(grid_pos.x + 1, grid_pos.y)),
+                Some(UVec2::new(grid_pos.x, grid_pos.y + 1)),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if neighbour.cmpge(self.dimensions).any() {
+                    continue;
+                }
+                let neighbour = self.idx(neighbour);
+                if self.obstacles[neighbour] == Obstacle::Stone {
+                    broken.insert(neighbour);
+                }
+            }
+        }
+
+        let mut broken: Vec<usize> = broken.into_iter().collect();
+        broken.sort_unstable();
+        for index in broken.iter() {
+            self.obstacles[*index] = Obstacle::None;
+        }
+        broken
+    }
+
+    /// Checks whether swapping the two grid positions would create a match.
+    pub fn swap_makes_match(&self, first: UVec2, second: UVec2) -> bool {
+        let mut scratch = self.clone();
+        scratch.swap(self.idx(first), self.idx(second));
+        !scratch.find_matches().is_empty()
+    }
+
+    /// Only tiles can be swapped, and locked or chained tiles stay where they
+    /// are.
+    pub fn can_swap(&self, first: UVec2, second: UVec2) -> bool {
+        let is_free = |grid_pos| {
+            matches!(self.get_tile(grid_pos), Some(tile) if !tile.is_locked())
+                && !self.is_fixed(self.idx(grid_pos))
+        };
+        is_free(first) && is_free(second)
+    }
+
+    /// A swap is valid if it creates a match or moves a special tile.
+    pub fn is_valid_swap(&self, first: UVec2, second: UVec2) -> bool {
+        if !self.can_swap(first, second) {
+            return false;
+        }
+        let is_special =
+            |grid_pos| matches!(self.get_tile(grid_pos), Some(tile) if tile.is_special());
+        is_special(first) || is_special(second) || self.swap_makes_match(first, second)
+    }
+
+    /// Lists every adjacent swap that would create a match or set off a special.
+    pub fn find_valid_moves(&self) -> Vec<(UVec2, UVec2)> {
+        let mut moves = Vec::new();
+        for y in 0..self.dimensions.y {
+            for x in 0..self.dimensions.x {
+                let grid_pos = UVec2::new(x, y);
+                if self.get_tile(grid_pos).is_none() {
+                    continue;
+                }
+                for neighbour in [UVec2::new(x + 1, y), UVec2::new(x, y + 1)] {
+                    if neighbour.x >= self.dimensions.x || neighbour.y >= self.dimensions.y {
+                        continue;
+                    }
+                    if self.get_tile(neighbour).is_some() && self.is_valid_swap(grid_pos, neighbour)
+                    {
+                        moves.push((grid_pos, neighbour));
+                    }
+                }
+            }
+        }
+        moves
+    }
+
+    /// Rearranges the existing tiles into a layout with no ready-made matches
+    /// and at least one valid move. Locked and chained tiles keep their place.
+    /// Returns None if no such layout was found.
+    pub fn shuffle(&mut self, rng: &mut impl Rng) -> Option<Vec<TileMove>> {
+        let occupied: Vec<usize> = (0..self.forward.len())
+            .filter(|index| matches!(self.forward[*index], Some(tile) if !tile.is_locked()))
+            .filter(|index| !self.is_fixed(*index))
+            .collect();
+        let mut targets = occupied.clone();
+
+        for _ in 0..MAX_SHUFFLE_ATTEMPTS {
+            targets.shuffle(rng);
+            let mut scratch = self.clone();
+            for (from, to) in occupied.iter().zip(targets.iter()) {
+                scratch.forward[*to] = self.forward[*from];
+            }
+            if scratch.find_matches().is_empty() && !scratch.find_valid_moves().is_empty() {
+                self.forward = scratch.forward;
+                let moves = occupied
+                    .iter()
+                    .zip(targets.iter())
+                    .filter(|(from, to)| from != to)
+                    .map(|(from, to)| TileMove {
+                        from: *from,
+                        to: *to,
+                    })
+                    .collect();
+                return Some(moves);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::level::{Level, LevelGoal, LevelProgress};
+    use crate::tile::TileMarking;
+    use crate::{MATCH_RULE, MIN_MATCH_LENGTH};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    pub(crate) fn plain_tile(color: usize) -> TileDesc {
+        TileDesc {
+            color: num::FromPrimitive::from_usize(color).unwrap(),
+            mark: TileMarking::Blank,
+            special: TileSpecial::None,
+        }
+    }
+
+    /// A board of plain tiles. Rows are listed top first and every character
+    /// is a colour index in base 36, like TestGame::set_layout.
+    fn board_from_rows(rows: &[&str]) -> Board {
+        let dimensions = UVec2::new(rows[0].len() as u32, rows.len() as u32);
+        let mut board = Board::new(dimensions, MIN_MATCH_LENGTH, MATCH_RULE);
+        for (row, line) in rows.iter().enumerate() {
+            let y = dimensions.y - 1 - row as u32;
+            for (x, character) in line.chars().enumerate() {
+                let index = board.idx((x as u32, y));
+                board.forward[index] = Some(plain_tile(character.to_digit(36).unwrap() as usize));
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn shuffle_keeps_locked_and_chained_tiles_and_leaves_a_move() {
+        let mut board = board_from_rows(&["0123", "1230", "2301", "3012"]);
+        let locked = board.idx((0, 0));
+        board.forward[locked].as_mut().unwrap().mark = TileMarking::Cross;
+        let chained = board.idx((3, 3));
+        board.add_obstacle(chained, Obstacle::Chain);
+        let before = board.clone();
+
+        let moves = board
+            .shuffle(&mut ChaCha8Rng::seed_from_u64(1))
+            .expect("no layout found");
+        assert!(!moves.is_empty());
+        assert!(moves
+            .iter()
+            .all(|tile_move| ![locked, chained].contains(&tile_move.from)
+                && ![locked, chained].contains(&tile_move.to)));
+        assert_eq!(board.forward[locked], before.forward[locked]);
+        assert_eq!(board.forward[chained], before.forward[chained]);
+        assert!(board.find_matches().is_empty());
+        assert!(!board.find_valid_moves().is_empty());
+
+        // Every tile is still there, just somewhere else.
+        let mut tiles: Vec<usize> = board
+            .forward
+            .iter()
+            .flatten()
+            .map(|tile| tile.get_index())
+            .collect();
+        let mut tiles_before: Vec<usize> = before
+            .forward
+            .iter()
+            .flatten()
+            .map(|tile| tile.get_index())
+            .collect();
+        tiles.sort_unstable();
+        tiles_before.sort_unstable();
+        assert_eq!(tiles, tiles_before);
+    }
+
+    #[test]
+    fn tiles_fall_past_holes_and_slide_into_capped_columns() {
+        // A hole in the middle, and one in the top left that caps column 0.
+        let mut board = Board::new(UVec2::new(3, 3), MIN_MATCH_LENGTH, MATCH_RULE);
+        board.add_hole(board.idx((1, 1)));
+        board.add_hole(board.idx((0, 2)));
+        let mut colors = 0..;
+        board.fill(|| plain_tile(colors.next().unwrap()));
+
+        let bottom_left = board.idx((0, 0));
+        let bottom_middle = board.idx((1, 0));
+        board.remove_tiles(&HashSet::from([bottom_left, bottom_middle]));
+        let falls = board.apply_gravity();
+        assert!(falls.contains(&TileMove {
+            from: board.idx((1, 2)),
+            to: bottom_middle,
+        }));
+        assert!(falls.contains(&TileMove {
+            from: board.idx((0, 1)),
+            to: bottom_left,
+        }));
+
+        // Nothing can drop into (0, 1), so it waits for column 1 to refill
+        // and takes the new tile from there.
+        let spawns = board.refill(|| plain_tile(9));
+        assert_eq!(spawns.len(), 1);
+        assert!(board.can_fall());
+        assert_eq!(
+            board.apply_gravity(),
+            vec![TileMove {
+                from: board.idx((1, 2)),
+                to: board.idx((0, 1)),
+            }]
+        );
+        board.refill(|| plain_tile(10));
+        assert!(!board.has_empty_cells());
+        assert!(!board.can_fall());
+    }
+
+    #[test]
+    fn obstacles_break_when_matched_or_next_to_a_match() {
+        let mut board = Board::new(UVec2::new(4, 3), MIN_MATCH_LENGTH, MATCH_RULE);
+        let mut colors = 2..;
+        board.fill(|| plain_tile(colors.next().unwrap()));
+        let bottom_row: Vec<usize> = (0..4).map(|x| board.idx((x, 0))).collect();
+        for index in &bottom_row[..3] {
+            board.forward[*index] = Some(plain_tile(0));
+        }
+        board.add_obstacle(bottom_row[0], Obstacle::Ice);
+        board.add_obstacle(bottom_row[1], Obstacle::Chain);
+        board.add_obstacle(bottom_row[3], Obstacle::Stone);
+        let far_stone = board.idx((3, 1));
+        board.add_obstacle(far_stone, Obstacle::Stone);
+        assert!(!board.can_swap(UVec2::new(1, 0), UVec2::new(1, 1)));
+
+        let resolution = board.clear_matches(&[], HashSet::new());
+        assert_eq!(resolution.removed, bottom_row[..3]);
+        assert_eq!(
+            resolution.broken,
+            vec![bottom_row[0], bottom_row[1], bottom_row[3]]
+        );
+        assert_eq!(board.obstacles[far_stone], Obstacle::Stone);
+
+        // The stone left standing holds up the tile above it, so the cell
+        // under it is filled from the side.
+        let above_stone = board.forward[board.idx((3, 2))];
+        board.apply_gravity();
+        assert_eq!(board.forward[board.idx((3, 2))], above_stone);
+        assert!(board.forward[bottom_row[3]].is_some());
+    }
+
+    #[test]
+    fn ingredients_drop_in_and_are_collected_at_the_bottom() {
+        let level = Level {
+            goals: vec![LevelGoal::CollectIngredients(2)],
+            layout: ["..S", "...", "..."].map(String::from).to_vec(),
+            ..Default::default()
+        };
+        let mut progress = LevelProgress::new(0, &level);
+        assert_eq!(progress.ingredient_columns, vec![2]);
+
+        let mut board = Board::new(UVec2::new(3, 3), MIN_MATCH_LENGTH, MATCH_RULE);
+        let mut colors = 0..;
+        board.fill(|| plain_tile(colors.next().unwrap()));
+        let column: Vec<usize> = (0..3).map(|y| board.idx((2, y))).collect();
+        board.remove_tiles(&HashSet::from([column[0]]));
+        board.apply_gravity();
+        let ingredient = progress.drop_ingredient(&mut board).unwrap();
+        assert_eq!(ingredient.index, column[2]);
+        assert!(board.refill(|| plain_tile(9)).is_empty());
+        assert_eq!(board.count_ingredients(), 1);
+        assert_eq!(progress.ingredients_left, 1);
+
+        // Nothing is collected until the ingredient reaches the bottom row,
+        // and it never matches or gets cleared along the way.
+        assert!(board.clear_matches(&[], HashSet::new()).is_empty());
+        board.remove_tiles(&HashSet::from([column[0], column[1]]));
+        board.apply_gravity();
+        assert!(board.forward[column[0]].unwrap().is_ingredient());
+        let resolution = board.clear_matches(&[], HashSet::new());
+        assert_eq!(resolution.collected, vec![column[0]]);
+        assert!(resolution.removed.is_empty());
+        assert_eq!(board.count_ingredients(), 0);
+    }
+
+    #[test]
+    fn ingredients_drop_in_at_the_top_of_an_emptied_column() {
+        let level = Level {
+            goals: vec![LevelGoal::CollectIngredients(1)],
+            layout: ["..S", "...", "..."].map(String::from).to_vec(),
+            ..Default::default()
+        };
+        let mut progress = LevelProgress::new(0, &level);
+        let mut board = Board::new(UVec2::new(3, 3), MIN_MATCH_LENGTH, MATCH_RULE);
+        let mut colors = 0..;
+        board.fill(|| plain_tile(colors.next().unwrap()));
+
+        // A column clearer takes out the whole spawner column.
+        let column: Vec<usize> = (0..3).map(|y| board.idx((2, y))).collect();
+        board.remove_tiles(&column.iter().copied().collect());
+        assert!(progress.drop_ingredient(&mut board).is_some());
+        assert_eq!(board.refill(|| plain_tile(9)).len(), 2);
+
+        assert!(board.forward[column[2]].unwrap().is_ingredient());
+        assert_eq!(board.count_ingredients(), 1);
+        assert!(board
+            .clear_matches(&[], HashSet::new())
+            .collected
+            .is_empty());
+    }
+
+    /// The one shape on the board and the special tile it earns.
+    fn only_shape(rows: &[&str], focus: &[(u32, u32)]) -> (Board, MatchShape) {
+        let board = board_from_rows(rows);
+        let focus: Vec<usize> = focus.iter().map(|grid_pos| board.idx(*grid_pos)).collect();
+        let mut shapes = board.find_shapes(&focus);
+        assert_eq!(shapes.len(), 1);
+        let shape = shapes.remove(0);
+        (board, shape)
+    }
+
+    #[test]
+    fn match_shapes_earn_special_tiles() {
+        // The a/b checkerboard around each shape never lines up by itself.
+        let (_, three) = only_shape(&["ababa", "babab", "ababa", "babab", "000ab"], &[]);
+        assert_eq!(three.special, TileSpecial::None);
+
+        let (board, row) = only_shape(&["ababa", "babab", "ababa", "babab", "0000a"], &[(1, 0)]);
+        assert_eq!(row.special, TileSpecial::RowClearer);
+        assert_eq!(row.anchor, board.idx((1, 0)));
+
+        let (board, column) = only_shape(&["ababa", "0abab", "0baba", "0abab", "0baba"], &[]);
+        assert_eq!(column.special, TileSpecial::ColumnClearer);
+        assert_eq!(column.anchor, board.idx((0, 2)));
+
+        let (board, l_shape) = only_shape(&["0abab", "0baba", "000ab", "babab", "ababa"], &[]);
+        assert_eq!(l_shape.special, TileSpecial::Bomb);
+        assert_eq!(l_shape.tiles.len(), 5);
+        assert_eq!(l_shape.anchor, board.idx((0, 2)));
+
+        let (board, t_shape) = only_shape(&["000ba", "b0bab", "a0aba", "babab", "ababa"], &[]);
+        assert_eq!(t_shape.special, TileSpecial::Bomb);
+        assert_eq!(t_shape.anchor, board.idx((1, 4)));
+
+        let (_, five) = only_shape(&["ababa", "babab", "ababa", "babab", "00000"], &[]);
+        assert_eq!(five.special, TileSpecial::ColorBomb);
+    }
+
+    #[test]
+    fn cleared_special_tiles_set_each_other_off() {
+        let mut board = board_from_rows(&["ababa", "babab", "ababa", "babab", "000ab"]);
+        let bottom_left = board.idx((0, 0));
+        let top_left = board.idx((0, 4));
+        board.forward[bottom_left].as_mut().unwrap().special = TileSpecial::ColumnClearer;
+        board.forward[top_left].as_mut().unwrap().special = TileSpecial::RowClearer;
+
+        // The match sets off the column clearer, which takes the row clearer
+        // at the top with it, which clears the top row.
+        let resolution = board.clear_matches(&[], HashSet::new());
+        let mut expected: Vec<usize> = (0..5).map(|y| board.idx((0, y))).collect();
+        expected.extend((1..5).map(|x| board.idx((x, 4))));
+        expected.extend([board.idx((1, 0)), board.idx((2, 0))]);
+        expected.sort_unstable();
+        assert_eq!(resolution.removed, expected);
+    }
+
+    #[test]
+    fn special_tile_is_created_where_the_player_swapped() {
+        let mut board = board_from_rows(&["ababa", "babab", "ababa", "babab", "0000a"]);
+        let focus = board.idx((2, 0));
+        let resolution = board.clear_matches(&[focus], HashSet::new());
+        assert_eq!(resolution.upgraded.len(), 1);
+        assert_eq!(resolution.upgraded[0].index, focus);
+        assert_eq!(resolution.removed.len(), 3);
+        assert_eq!(
+            board.forward[focus].map(|tile| tile.special),
+            Some(TileSpecial::RowClearer)
+        );
+    }
+
+    #[test]
+    fn match_rules_decide_what_lines_up() {
+        let tile = |color: usize, mark: TileMarking| TileDesc {
+            mark,
+            ..plain_tile(color)
+        };
+        let same_color = [
+            tile(0, TileMarking::Star),
+            tile(0, TileMarking::Circle),
+            tile(0, TileMarking::Square),
+        ];
+        let same_mark = [
+            tile(0, TileMarking::Star),
+            tile(1, TileMarking::Star),
+            tile(2, TileMarking::Star),
+        ];
+        let same_both = [tile(3, TileMarking::Star); 3];
+        let lines_up = |rule: MatchRule, tiles: [TileDesc; 3]| {
+            let mut board = Board::new(UVec2::new(3, 1), MIN_MATCH_LENGTH, rule);
+            for (index, tile) in tiles.into_iter().enumerate() {
+                board.forward[index] = Some(tile);
+            }
+            !board.find_runs().is_empty()
+        };
+
+        for (rule, expected) in [
+            (MatchRule::Color, [true, false, true]),
+            (MatchRule::Mark, [false, true, true]),
+            (MatchRule::ColorAndMark, [false, false, true]),
+        ] {
+            let found = [same_color, same_mark, same_both].map(|tiles| lines_up(rule, tiles));
+            assert_eq!(found, expected, "{:?}", rule);
+        }
+        assert!(!TileDesc::ingredient().matches(&TileDesc::ingredient(), MatchRule::Color));
+    }
+
+    #[test]
+    fn cross_tiles_are_locked() {
+        let mut board = board_from_rows(&["012", "120"]);
+        let locked = board.idx((0, 0));
+        board.forward[locked].as_mut().unwrap().mark = TileMarking::Cross;
+        assert!(!board.can_swap(UVec2::new(0, 0), UVec2::new(1, 0)));
+        assert!(board.can_swap(UVec2::new(1, 0), UVec2::new(2, 0)));
+        assert!(TileMarking::Star.bonus_points() > 0);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::*;
+use bevy::app::AppExit;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const SAVE_FILE: &str = "savegame.ron";
+pub const ZEN_SAVE_FILE: &str = "zen.ron";
+
+/// Saves the game in progress when the app closes and brings it back when
+/// the player picks Continue from the main menu. Zen games have a save of
+/// their own, picked back up by the Zen button.
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveDir>()
+            .add_systems(
+                OnEnter(GameState::InGame),
+                finish_resume
+                    .after(fill_gameboard)
+                    .after(reset_score)
+                    .run_if(resource_exists::<ResumeGame>),
+            )
+            .add_systems(OnEnter(GameState::GameOver), delete_save)
+            .add_systems(
+                OnExit(GameState::InGame),
+                save_on_leave
+                    .before(teardown_gameboard)
+                    .run_if(resource_exists::<GameBoard>)
+                    .run_if(in_zen_mode),
+            )
+            .add_systems(
+                Last,
+                autosave_on_exit
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(resource_exists::<GameBoard>),
+            );
+    }
+}
+
+/// Everything needed to pick a game back up. The RNG is stored as its seed
+/// and how far into the sequence it got, so refills carry on as they would
+/// have.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SaveGame {
+    pub config: GameConfig,
+    pub seed: u64,
+    pub rng_word_pos: u128,
+    pub tiles: Vec<Option<TileDesc>>,
+    /// The cells cut out of the board, sorted.
+    #[serde(default)]
+    pub holes: Vec<usize>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    pub score: u64,
+    /// The level being played, if any.
+    #[serde(default)]
+    pub progress: Option<LevelProgress>,
+}
+
+/// Where saves are kept, the working directory unless one is set up front.
+/// Tests point it at a directory of their own.
+#[derive(Resource, Clone, Debug)]
+pub struct SaveDir(pub PathBuf);
+
+impl Default for SaveDir {
+    fn default() -> Self {
+        SaveDir(PathBuf::from("."))
+    }
+}
+
+impl SaveDir {
+    /// The save a game mode is kept in. An arcade round is over in minutes,
+    /// so it isn't kept.
+    pub fn save_file(&self, mode: GameMode) -> Option<PathBuf> {
+        match mode {
+            GameMode::Levels => Some(self.0.join(SAVE_FILE)),
+            GameMode::Zen => Some(self.0.join(ZEN_SAVE_FILE)),
+            GameMode::Arcade => None,
+        }
+    }
+}
+
+/// A save being loaded. The board is built from it instead of being dealt
+/// and it is removed once the game has started.
+#[derive(Resource)]
+pub struct ResumeGame(pub SaveGame);
+
+impl SaveGame {
+    pub fn capture(
+        board: &Board,
+        score: &Score,
+        rng: &GameRng,
+        config: &GameConfig,
+        pending_swap: Option<&PendingSwap>,
+        progress: Option<&LevelProgress>,
+    ) -> SaveGame {
+        let mut tiles = board.forward.clone();
+        // A swap that hasn't been resolved yet is undone, resuming from the
+        // board as it was before it.
+        if let Some(pending_swap) = pending_swap {
+            tiles.swap(
+                board.idx(pending_swap.first),
+                board.idx(pending_swap.second),
+            );
+        }
+        let mut holes: Vec<usize> = board.holes.iter().copied().collect();
+        holes.sort_unstable();
+        SaveGame {
+            config: config.clone(),
+            seed: rng.seed,
+            rng_word_pos: rng.rng.get_word_pos(),
+            tiles,
+            holes,
+            obstacles: board.obstacles.clone(),
+            score: score.value,
+            progress: progress.cloned(),
+        }
+    }
+
+    pub fn game_rng(&self) -> GameRng {
+        GameRng::resume(self.seed, self.rng_word_pos)
+    }
+
+    pub fn load(path: &Path) -> Result<SaveGame, String> {
+        let contents = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        ron::from_str(&contents).map_err(|error| error.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+        std::fs::write(path, contents).map_err(|error| error.to_string())
+    }
+}
+
+/// Everything SaveGame::capture reads from the world.
+#[derive(SystemParam)]
+pub struct CurrentGame<'w> {
+    game_board: Res<'w, GameBoard>,
+    score: Res<'w, Score>,
+    rng: Res<'w, GameRng>,
+    config: Res<'w, GameConfig>,
+    pending_swap: Option<Res<'w, PendingSwap>>,
+    progress: Option<Res<'w, LevelProgress>>,
+    save_dir: Res<'w, SaveDir>,
+}
+
+impl CurrentGame<'_> {
+    pub fn save(&self) {
+        let save_game = SaveGame::capture(
+            &self.game_board,
+            &self.score,
+            &self.rng,
+            &self.config,
+            self.pending_swap.as_deref(),
+            self.progress.as_deref(),
+        );
+        let Some(path) = self.save_dir.save_file(self.config.mode) else {
+            return;
+        };
+        match save_game.save(&path) {
+            Ok(()) => info!("Saved game to {}", path.display()),
+            Err(error) => warn!("Could not save game to {}: {}", path.display(), error),
+        }
+    }
+}
+
+pub fn finish_resume(mut commands: Commands, resume: Res<ResumeGame>, mut score: ResMut<Score>) {
+    score.value = resume.0.score;
+    commands.remove_resource::<ResumeGame>();
+
+    info!("Resumed game with score {}", score.value);
+}
+
+pub fn autosave_on_exit(mut app_exit: EventReader<AppExit>, current_game: CurrentGame) {
+    if app_exit.is_empty() {
+        return;
+    }
+    app_exit.clear();
+    current_game.save();
+}
+
+/// A zen game is kept whenever the player leaves it, not only on quitting.
+pub fn save_on_leave(current_game: CurrentGame) {
+    current_game.save();
+}
+
+/// A finished game can't be continued. Only its own mode's save goes, so
+/// e.g. an arcade round never loses the zen game.
+pub fn delete_save(save_dir: Res<SaveDir>, config: Res<GameConfig>) {
+    let Some(path) = save_dir.save_file(config.mode) else {
+        return;
+    };
+    if path.exists() {
+        if let Err(error) = std::fs::remove_file(&path) {
+            warn!("Could not delete {}: {}", path.display(), error);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::*;
+use bevy::prelude::*;
+
+const POINTS_PER_TILE: u64 = 10;
+const MATCH_4_BONUS: u64 = 50;
+const MATCH_5_BONUS: u64 = 150;
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Score>()
+            .add_event::<MatchScoredEvent>()
+            .add_systems(OnEnter(GameState::InGame), (reset_score, spawn_score_hud))
+            .add_systems(OnExit(GameState::InGame), despawn_screen::<ScoreText>)
+            .add_systems(
+                Update,
+                (score_matches, update_score_hud)
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct Score {
+    pub value: u64,
+    pub chain: u32,
+}
+
+#[derive(Event, Debug)]
+pub struct MatchScoredEvent {
+    pub points: u64,
+    pub tiles: usize,
+    pub chain: u32,
+}
+
+#[derive(Component)]
+pub struct ScoreText;
+
+/// Points for a single cascade step. Every cleared tile is worth the same plus
+/// its marking bonus, longer runs earn a bonus and the whole lot is multiplied
+/// by the cascade step.
+pub fn score_runs(runs: &[Vec<usize>], cleared: &[TileDesc], cascade: u32) -> u64 {
+    let bonus: u64 = runs
+        .iter()
+        .map(|run| match run.len() {
+            0..=3 => 0,
+            4 => MATCH_4_BONUS,
+            _ => MATCH_5_BONUS,
+        })
+        .sum();
+
+    let tile_points: u64 = cleared
+        .iter()
+        .map(|tile| POINTS_PER_TILE + tile.mark.bonus_points())
+        .sum();
+
+    (tile_points + bonus) * cascade.max(1) as u64
+}
+
+pub fn score_matches(
+    mut matched: EventReader<TilesMatchedEvent>,
+    mut scored: EventWriter<MatchScoredEvent>,
+    mut score: ResMut<Score>,
+) {
+    for event in matched.read() {
+        let points = score_runs(&event.runs, &event.cleared, event.cascade);
+        score.value += points;
+        score.chain = event.cascade;
+
+        scored.send(MatchScoredEvent {
+            points,
+            tiles: event.cleared.len(),
+            chain: event.cascade,
+        });
+
+        info!(
+            "Scored {} points for {} tiles at chain x{}",
+            points,
+            event.cleared.len(),
+            event.cascade
+        );
+    }
+}
+
+pub fn reset_score(mut score: ResMut<Score>) {
+    *score = Score::default();
+}
+
+pub fn spawn_score_hud(mut commands: Commands) {
+    commands
+        .spawn(TextBundle {
+            text: Text::from_sections([
+                TextSection::new(
+                    "Score: 0",
+                    TextStyle {
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font_size: 30.0,
+                        color: Color::GOLD,
+                        ..default()
+                    },
+                ),
+            ]),
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(20.0),
+                left: Val::Px(20.0),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Name::new("Score"))
+        .insert(ScoreText);
+}
+
+pub fn update_score_hud(score: Res<Score>, mut text_query: Query<&mut Text, With<ScoreText>>) {
+    if !score.is_changed() {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("Score: {}", score.value);
+        text.sections[1].value = if score.chain > 1 {
+            format!("\nChain x{}", score.chain)
+        } else {
+            String::new()
+        };
+    }
+}
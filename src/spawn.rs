@@ -0,0 +1,270 @@
+use crate::*;
+use bevy::prelude::*;
+use rand::distributions::WeightedIndex;
+use serde::{Deserialize, Serialize};
+
+/// How many times a strategy rerolls before settling for what it has.
+const MAX_SPAWN_ATTEMPTS: u32 = 20;
+
+/// Picks where new tiles come from, both for the deal and for refills. The
+/// strategy is chosen from the config's spawn rule when a game starts, see
+/// spawn_strategy.
+pub struct SpawnPlugin;
+
+impl Plugin for SpawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::InGame),
+            choose_spawn_strategy
+                .after(start_level)
+                .before(seed_game_rng),
+        );
+    }
+}
+
+/// Which spawn strategy the game uses.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SpawnRule {
+    /// Every colour is as likely as the next.
+    #[default]
+    Uniform,
+    /// Colours are picked by the level's colour weights.
+    Weighted,
+    /// Weighted, but new tiles don't land in a match if they can help it.
+    AvoidMatches,
+    /// Weighted, and every refill leaves at least one move on the board.
+    GuaranteeMove,
+}
+
+/// Decides what each new tile is. Strategies only see the pure board, so
+/// they can be tried out without running the game.
+pub trait SpawnStrategy: Send + Sync {
+    /// The tile to put in the empty cell at index.
+    fn pick(&self, board: &Board, index: usize, colors: u32, rng: &mut dyn RngCore) -> TileDesc;
+
+    /// Fills the board's refill cells one at a time, in the same order as
+    /// Board::refill.
+    fn refill(&self, board: &mut Board, colors: u32, rng: &mut dyn RngCore) -> Vec<TileSpawn> {
+        let mut spawns = Vec::new();
+        for index in board.refill_cells() {
+            let tile = self.pick(board, index, colors, rng);
+            board.forward[index] = Some(tile);
+            spawns.push(TileSpawn { index, tile });
+        }
+        spawns
+    }
+
+    /// Fills every cell of a new board in index order, like Board::fill.
+    fn deal(&self, board: &mut Board, colors: u32, rng: &mut dyn RngCore) {
+        for index in 0..board.forward.len() {
+            if board.holds_tiles(index) {
+                board.forward[index] = Some(self.pick(board, index, colors, rng));
+            }
+        }
+    }
+}
+
+/// Any colour, any mark, just like TileDesc::random.
+pub struct Uniform;
+
+impl SpawnStrategy for Uniform {
+    fn pick(&self, _board: &Board, _index: usize, colors: u32, rng: &mut dyn RngCore) -> TileDesc {
+        TileDesc::random(rng, colors)
+    }
+}
+
+/// Colours by relative weight, first colour first. Colours past the end of
+/// the weights count as 1.
+pub struct Weighted {
+    pub weights: Vec<u32>,
+}
+
+impl SpawnStrategy for Weighted {
+    fn pick(&self, board: &Board, index: usize, colors: u32, rng: &mut dyn RngCore) -> TileDesc {
+        let weights =
+            (0..colors as usize).map(|color| self.weights.get(color).copied().unwrap_or(1));
+        // All zero weights leave nothing to pick from.
+        let Ok(distribution) = WeightedIndex::new(weights) else {
+            return Uniform.pick(board, index, colors, rng);
+        };
+        let color: TileColor = num::FromPrimitive::from_usize(rng.sample(&distribution)).unwrap();
+        TileDesc::with_random_mark(rng, color)
+    }
+}
+
+/// Rerolls tiles that would complete a match where they land.
+pub struct AvoidMatches(pub Box<dyn SpawnStrategy>);
+
+impl SpawnStrategy for AvoidMatches {
+    fn pick(&self, board: &Board, index: usize, colors: u32, rng: &mut dyn RngCore) -> TileDesc {
+        let mut tile = self.0.pick(board, index, colors, rng);
+        for _ in 1..MAX_SPAWN_ATTEMPTS {
+            if !board.makes_match_at(index, tile) {
+                break;
+            }
+            tile = self.0.pick(board, index, colors, rng);
+        }
+        tile
+    }
+}
+
+/// Refills the whole board again until it is left with a move to make.
+pub struct GuaranteeMove(pub Box<dyn SpawnStrategy>);
+
+impl SpawnStrategy for GuaranteeMove {
+    fn pick(&self, board: &Board, index: usize, colors: u32, rng: &mut dyn RngCore) -> TileDesc {
+        self.0.pick(board, index, colors, rng)
+    }
+
+    fn refill(&self, board: &mut Board, colors: u32, rng: &mut dyn RngCore) -> Vec<TileSpawn> {
+        let mut refilled = board.clone();
+        let mut spawns = self.0.refill(&mut refilled, colors, rng);
+        for _ in 1..MAX_SPAWN_ATTEMPTS {
+            if spawns.is_empty() || !refilled.find_valid_moves().is_empty() {
+                break;
+            }
+            refilled = board.clone();
+            spawns = self.0.refill(&mut refilled, colors, rng);
+        }
+        *board = refilled;
+        spawns
+    }
+}
+
+/// The strategy for a spawn rule. Without weights, or with fewer than two
+/// colours in play weighted, the weighted rules build on Uniform.
+pub fn spawn_strategy(rule: SpawnRule, weights: &[u32], colors: u32) -> Box<dyn SpawnStrategy> {
+    let weighted = (0..colors as usize)
+        .filter(|color| weights.get(*color).copied().unwrap_or(1) > 0)
+        .count();
+    if !weights.is_empty() && weighted < 2 {
+        warn!("Colour weights {weights:?} leave fewer than two colours, spawning uniformly");
+    }
+    let base = || -> Box<dyn SpawnStrategy> {
+        if weights.is_empty() || weighted < 2 {
+            Box::new(Uniform)
+        } else {
+            Box::new(Weighted {
+                weights: weights.to_vec(),
+            })
+        }
+    };
+    match rule {
+        SpawnRule::Uniform => Box::new(Uniform),
+        SpawnRule::Weighted => base(),
+        SpawnRule::AvoidMatches => Box::new(AvoidMatches(base())),
+        SpawnRule::GuaranteeMove => Box::new(GuaranteeMove(base())),
+    }
+}
+
+/// The strategy the current game spawns tiles with.
+#[derive(Resource)]
+pub struct TileSpawner(pub Box<dyn SpawnStrategy>);
+
+pub fn choose_spawn_strategy(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    levels: Res<Levels>,
+    current_level: Res<CurrentLevel>,
+) {
+    let weights = match config.mode {
+        GameMode::Levels => levels
+            .0
+            .get(current_level.0)
+            .map(|level| level.color_weights.clone())
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    commands.insert_resource(TileSpawner(spawn_strategy(
+        config.spawn_rule,
+        &weights,
+        config.colors,
+    )));
+
+    info!("Spawning tiles with {:?}", config.spawn_rule);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::tests::plain_tile;
+    use rand_chacha::ChaCha8Rng;
+
+    /// A board with the given bottom rows, bottom row first, and the rest
+    /// left empty for the spawn strategies to fill.
+    fn board_with_rows(width: u32, height: u32, rows: &[&[usize]]) -> Board {
+        let mut board = Board::new(UVec2::new(width, height), MIN_MATCH_LENGTH, MATCH_RULE);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                let index = board.idx((x as u32, y as u32));
+                board.forward[index] = Some(plain_tile(*color));
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn uniform_and_weighted_spawns() {
+        let mut plain = board_with_rows(4, 4, &[&[0, 1, 2, 3]]);
+        let mut uniform = plain.clone();
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let plain_spawns = plain.refill(|| TileDesc::random(&mut rng, 5));
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let uniform_spawns = Uniform.refill(&mut uniform, 5, &mut rng);
+        assert_eq!(uniform_spawns, plain_spawns);
+        assert_eq!(uniform.forward, plain.forward);
+
+        let mut weighted = board_with_rows(4, 4, &[]);
+        let strategy = Weighted {
+            weights: vec![0, 3, 0],
+        };
+        strategy.refill(&mut weighted, 3, &mut rng);
+        assert!(weighted
+            .forward
+            .iter()
+            .all(|tile| tile.map(|tile| tile.color) == Some(TileColor::LightPink)));
+    }
+
+    #[test]
+    fn one_weighted_colour_spawns_uniformly() {
+        let strategy = spawn_strategy(SpawnRule::Weighted, &[0, 1, 0, 0, 0], 5);
+        let mut board = board_with_rows(4, 4, &[]);
+        strategy.refill(&mut board, 5, &mut ChaCha8Rng::seed_from_u64(7));
+        assert!(board
+            .forward
+            .iter()
+            .flatten()
+            .any(|tile| tile.color != TileColor::LightPink));
+    }
+
+    #[test]
+    fn avoid_matches_keeps_new_tiles_out_of_runs() {
+        let board = board_with_rows(3, 1, &[&[0, 0]]);
+        let empty = board.idx((2, 0));
+        assert!(board.makes_match_at(empty, plain_tile(0)));
+        assert!(!board.makes_match_at(empty, plain_tile(1)));
+
+        let strategy = AvoidMatches(Box::new(Uniform));
+        for seed in 0..10 {
+            let mut board = board.clone();
+            let spawns = strategy.refill(&mut board, 2, &mut ChaCha8Rng::seed_from_u64(seed));
+            assert_eq!(spawns.len(), 1);
+            assert!(board.find_matches().is_empty());
+        }
+    }
+
+    #[test]
+    fn guarantee_move_leaves_a_move_after_refill() {
+        let board = board_with_rows(3, 3, &[&[0, 1, 2], &[2, 0, 1]]);
+        assert!(board.find_valid_moves().is_empty());
+
+        let strategy = spawn_strategy(SpawnRule::GuaranteeMove, &[], 3);
+        for seed in 0..10 {
+            let mut board = board.clone();
+            let spawns = strategy.refill(&mut board, 3, &mut ChaCha8Rng::seed_from_u64(seed));
+            assert_eq!(spawns.len(), 3);
+            assert!(!board.has_empty_cells());
+            assert!(!board.find_valid_moves().is_empty());
+        }
+    }
+}
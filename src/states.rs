@@ -2,8 +2,33 @@ use bevy::prelude::*;
 
 #[derive(States, PartialEq, Eq, Debug, Clone, Hash, Default)]
 pub enum GameState {
+    /// Waiting for the level files, see LevelsLoaded.
     #[default]
+    Loading,
     MainMenu,
     InGame,
     GameOver,
 }
+
+/// Whether the game is paused. Game time stops while paused, so animations
+/// and timers stay where they are, and the board takes no input.
+#[derive(States, PartialEq, Eq, Debug, Clone, Hash, Default)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+
+/// Where the board is in resolving a swap. Input is only taken while Idle,
+/// after which a swap goes Swapping, Matching, Clearing, Falling, Refilling
+/// and back to Matching until nothing else matches.
+#[derive(States, PartialEq, Eq, Debug, Clone, Hash, Default)]
+pub enum ResolveState {
+    #[default]
+    Idle,
+    Swapping,
+    Matching,
+    Clearing,
+    Falling,
+    Refilling,
+}
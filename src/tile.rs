@@ -1,9 +1,10 @@
-use bevy::prelude::Component;
+use bevy::prelude::{Color, Component};
 
 use num_derive::FromPrimitive;
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, Serialize, Deserialize)]
 
 pub enum TileColor {
     LightYellow = 0,
@@ -26,7 +27,35 @@ pub enum TileColor {
     DarkGrey = 17,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive)]
+impl TileColor {
+    pub const COUNT: usize = 18;
+
+    /// Roughly the colour of the tile in the sprite sheet, for effects.
+    pub fn to_color(self) -> Color {
+        match self {
+            TileColor::LightYellow => Color::rgb(1.0, 0.95, 0.6),
+            TileColor::LightPink => Color::rgb(1.0, 0.75, 0.85),
+            TileColor::DarkYellow => Color::rgb(0.85, 0.7, 0.1),
+            TileColor::BrightPink => Color::rgb(1.0, 0.3, 0.7),
+            TileColor::DarkGreen => Color::rgb(0.1, 0.45, 0.15),
+            TileColor::Red => Color::rgb(0.9, 0.15, 0.15),
+            TileColor::Green => Color::rgb(0.2, 0.75, 0.25),
+            TileColor::DarkRed => Color::rgb(0.55, 0.05, 0.05),
+            TileColor::LightGreen => Color::rgb(0.6, 0.95, 0.55),
+            TileColor::Brown => Color::rgb(0.55, 0.35, 0.15),
+            TileColor::LightBlue => Color::rgb(0.55, 0.8, 1.0),
+            TileColor::Orange => Color::rgb(1.0, 0.55, 0.1),
+            TileColor::DarkBlue => Color::rgb(0.1, 0.15, 0.6),
+            TileColor::LightGrey => Color::rgb(0.8, 0.8, 0.8),
+            TileColor::DarkPurple => Color::rgb(0.35, 0.1, 0.5),
+            TileColor::Grey => Color::rgb(0.5, 0.5, 0.5),
+            TileColor::BrightPurple => Color::rgb(0.75, 0.3, 1.0),
+            TileColor::DarkGrey => Color::rgb(0.25, 0.25, 0.25),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, FromPrimitive, Serialize, Deserialize)]
 pub enum TileMarking {
     Blank = 0,
     Cross = 1,
@@ -36,25 +65,112 @@ pub enum TileMarking {
     Star = 5,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug, Component)]
+/// What two tiles need to have in common to count as a match.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum MatchRule {
+    #[default]
+    Color,
+    Mark,
+    ColorAndMark,
+}
+
+impl TileMarking {
+    /// Cross tiles are locked in place and can't be swapped.
+    pub fn is_locked(&self) -> bool {
+        *self == TileMarking::Cross
+    }
+
+    /// Seconds added to the clock for clearing a tile with this marking in
+    /// arcade mode.
+    pub fn bonus_seconds(&self) -> f32 {
+        match self {
+            TileMarking::Circle => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Extra points for clearing a tile with this marking.
+    pub fn bonus_points(&self) -> u64 {
+        match self {
+            TileMarking::Star => 25,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum TileSpecial {
+    #[default]
+    None,
+    RowClearer,
+    ColumnClearer,
+    Bomb,
+    ColorBomb,
+    /// Not a power but a piece to bring down to the bottom of the board. It
+    /// never matches and is collected once it gets there.
+    Ingredient,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Component, Serialize, Deserialize)]
 pub struct TileDesc {
     pub color: TileColor,
     pub mark: TileMarking,
+    pub special: TileSpecial,
 }
 
 impl TileDesc {
-    pub fn new() -> Self {
-        let random_color: usize = thread_rng().gen_range(0..17);
-        let random_mark: usize = thread_rng().gen_range(0..5);
+    /// A random plain tile using the first `colors` entries of the palette.
+    pub fn random(rng: &mut (impl Rng + ?Sized), colors: u32) -> Self {
+        let random_color: usize = rng.gen_range(0..colors as usize);
         let new_color: TileColor = num::FromPrimitive::from_usize(random_color).unwrap();
+        Self::with_random_mark(rng, new_color)
+    }
+
+    /// A plain tile of the given colour with a random marking.
+    pub fn with_random_mark(rng: &mut (impl Rng + ?Sized), color: TileColor) -> Self {
+        let random_mark: usize = rng.gen_range(0..6);
         let new_mark: TileMarking = num::FromPrimitive::from_usize(random_mark).unwrap();
 
         Self {
-            color: new_color,
+            color,
             mark: new_mark,
+            special: TileSpecial::None,
         }
     }
 
+    /// A collectible ingredient, which looks like a plain brown tile with a
+    /// marker on top.
+    pub fn ingredient() -> Self {
+        Self {
+            color: TileColor::Brown,
+            mark: TileMarking::Blank,
+            special: TileSpecial::Ingredient,
+        }
+    }
+
+    pub fn matches(&self, other: &TileDesc, rule: MatchRule) -> bool {
+        if self.is_ingredient() || other.is_ingredient() {
+            return false;
+        }
+        match rule {
+            MatchRule::Color => self.color == other.color,
+            MatchRule::Mark => self.mark == other.mark,
+            MatchRule::ColorAndMark => self.color == other.color && self.mark == other.mark,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.mark.is_locked()
+    }
+
+    pub fn is_special(&self) -> bool {
+        !matches!(self.special, TileSpecial::None | TileSpecial::Ingredient)
+    }
+
+    pub fn is_ingredient(&self) -> bool {
+        self.special == TileSpecial::Ingredient
+    }
+
     pub fn get_index(&self) -> usize {
         (self.color as usize * 6) + self.mark as usize
     }
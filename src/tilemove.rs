@@ -2,19 +2,11 @@ use crate::*;
 use bevy::prelude::*;
 use simple_easing::expo_in_out;
 
-#[derive(States, PartialEq, Eq, Debug, Clone, Hash, Default)]
-pub enum MoveState {
-    #[default]
-    NotMoving,
-    Moving,
-}
-
 pub struct TileMovePlugin;
 
 impl Plugin for TileMovePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PostUpdate, tile_mover)
-            .init_state::<MoveState>();
+        app.add_systems(PostUpdate, tile_mover);
     }
 }
 
@@ -22,14 +14,7 @@ pub fn tile_mover(
     mut commands: Commands,
     mut move_query: Query<(Entity, &mut Transform, &mut TileMoving), With<TileMoving>>,
     time: Res<Time>,
-    mut next_state: ResMut<NextState<MoveState>>,
 ) {
-    let mut num_tile_moving = move_query.iter().count();
-
-    if num_tile_moving > 0 {
-        next_state.set(MoveState::Moving);
-    }
-
     for (entity, mut transform, mut tile_move) in move_query.iter_mut() {
         // We tick the TileMoving timer along
         tile_move.duration.tick(time.delta());
@@ -40,7 +25,6 @@ pub fn tile_mover(
             transform.translation.x = tile_move.destination.x;
             transform.translation.y = tile_move.destination.y;
             commands.entity(entity).remove::<TileMoving>();
-            num_tile_moving -= 1;
             info!("Finished Moving Tile");
         } else {
             // Otherwise we update the tile's transform based on an easing function
@@ -48,12 +32,9 @@ pub fn tile_mover(
             let mut final_transform = tile_move.destination - tile_move.origin;
             let percent_complete = tile_move.duration.fraction();
             let eased_percent = expo_in_out(percent_complete);
-            final_transform = final_transform * eased_percent;
+            final_transform *= eased_percent;
             transform.translation.x = tile_move.origin.x + final_transform.x;
             transform.translation.y = tile_move.origin.y + final_transform.y;
         }
     }
-    if num_tile_moving == 0 {
-        next_state.set(MoveState::NotMoving);
-    }
 }
@@ -0,0 +1,71 @@
+use crate::*;
+use bevy::prelude::*;
+
+/// An endless game with no way to lose. It starts with a handful of colours
+/// and brings in another from the palette every ZEN_POINTS_PER_COLOR points,
+/// so it slowly gets harder. The game is saved whenever the player leaves.
+pub struct ZenPlugin;
+
+impl Plugin for ZenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::InGame),
+            (
+                start_zen
+                    .before(seed_game_rng)
+                    .run_if(not(resource_exists::<ResumeGame>)),
+                spawn_zen_hud,
+            )
+                .run_if(in_zen_mode),
+        )
+        .add_systems(OnExit(GameState::InGame), despawn_screen::<ZenText>)
+        .add_systems(
+            Update,
+            (grow_color_pool, update_zen_hud)
+                .chain()
+                .after(score_matches)
+                .run_if(in_zen_mode)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+pub fn in_zen_mode(config: Res<GameConfig>) -> bool {
+    config.mode == GameMode::Zen
+}
+
+/// How many colours new tiles are drawn from at the given score.
+pub fn zen_colors(score: u64) -> u32 {
+    let extra = (score / ZEN_POINTS_PER_COLOR).min(TileColor::COUNT as u64) as u32;
+    (ZEN_START_COLORS + extra).min(TileColor::COUNT as u32)
+}
+
+#[derive(Component)]
+pub struct ZenText;
+
+pub fn start_zen(mut config: ResMut<GameConfig>) {
+    config.colors = ZEN_START_COLORS;
+    info!("Started a zen game with {} colours", config.colors);
+}
+
+/// Only ever adds colours, so a resumed game carries on where it was.
+pub fn grow_color_pool(score: Res<Score>, mut config: ResMut<GameConfig>) {
+    if !score.is_changed() {
+        return;
+    }
+    let colors = zen_colors(score.value);
+    if colors > config.colors {
+        config.colors = colors;
+        info!("Brought in a new colour, now {}", colors);
+    }
+}
+
+pub fn spawn_zen_hud(mut commands: Commands) {
+    spawn_mode_hud(&mut commands, "Zen", ZenText);
+}
+
+pub fn update_zen_hud(config: Res<GameConfig>, mut text_query: Query<&mut Text, With<ZenText>>) {
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("Zen\nColours: {}/{}", config.colors, TileColor::COUNT);
+    }
+}